@@ -0,0 +1,4 @@
+/// Get a two if feature two is enabled
+pub fn value() -> i32 {
+    if cfg!(feature = "two") { 2 } else { 0 }
+}
@@ -0,0 +1,7 @@
+//? run --features "two"
+
+mod helper;
+
+fn main() {
+    assert_eq!(helper::value(), 2);
+}
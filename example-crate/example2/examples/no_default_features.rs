@@ -0,0 +1,5 @@
+//? run --no-default-features
+
+fn main() {
+    assert!(!cfg!(feature = "one"));
+}
@@ -0,0 +1,4 @@
+fn main() {
+    assert!(!cfg!(feature = "one"));
+    assert!(cfg!(feature = "two"));
+}
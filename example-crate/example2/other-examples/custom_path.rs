@@ -0,0 +1,5 @@
+//? run --features "two"
+
+fn main() {
+    assert!(cfg!(feature = "two"));
+}
@@ -0,0 +1,306 @@
+//! Compare the times taken by runs against a saved baseline
+
+use crate::{
+    Outcome, RunResult, cargo_toml,
+    error::Error,
+    plan,
+    state::{self, RunId},
+    usage::format_duration,
+};
+use serde_json::{Value, json};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Check that a baseline name can be used as a file name
+pub fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        Err(Error::new(format!(
+            "Invalid baseline name \"{name}\": names can only contain letters, numbers, '-', '_' and '.'"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the file that a baseline is stored in
+fn baseline_file(dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    check_name(name)?;
+    Ok(cargo_toml::join(
+        &cargo_toml::join(
+            &cargo_toml::join(&cargo_toml::target_dir(&dir)?, "templated-examples"),
+            "baselines",
+        ),
+        &format!("{name}.json"),
+    ))
+}
+
+/// Get the time taken by a successful run
+///
+/// In bench mode, this is the median of the timed runs. Otherwise, it is the time of the attempt
+/// that succeeded, so the failed attempts of a flaky run are not included.
+fn time(r: &RunResult) -> Option<Duration> {
+    if !matches!(r.outcome, Outcome::Passed | Outcome::Flaky) || r.samples.is_empty() {
+        return None;
+    }
+    let mut samples = r.samples.clone();
+    samples.sort();
+    Some(samples[samples.len() / 2])
+}
+
+/// Describe a run by its example, template bindings, build type, target and feature set
+fn label(id: &RunId) -> String {
+    let mut label = id.example.clone();
+    let mut details = id
+        .bindings
+        .iter()
+        .map(|(n, v)| format!("{n}={v}"))
+        .collect::<Vec<_>>();
+    if let Some(b) = &id.build {
+        details.push(b.clone());
+    }
+    if let Some(t) = &id.target {
+        details.push(t.clone());
+    }
+    if !details.is_empty() {
+        label.push_str(&format!(" ({})", details.join(", ")));
+    }
+    if let Some(f) = &id.feature_set {
+        label.push_str(&format!(" {}", plan::describe_feature_set(f)));
+    }
+    label
+}
+
+/// Parse a threshold given as a percentage, eg "10%"
+pub fn parse_threshold(threshold: &str) -> Result<f64, Error> {
+    threshold
+        .strip_suffix('%')
+        .unwrap_or(threshold)
+        .parse::<f64>()
+        .ok()
+        .filter(|t| *t >= 0.0)
+        .map(|t| t / 100.0)
+        .ok_or_else(|| {
+            Error::new(format!(
+                "Invalid threshold \"{threshold}\": thresholds must be a percentage, eg 10%"
+            ))
+        })
+}
+
+/// Save the times taken by successful runs as a baseline
+pub fn save(dir: &Path, name: &str, results: &[RunResult]) -> Result<(), Error> {
+    let file = baseline_file(dir, name)?;
+    let runs = results
+        .iter()
+        .filter_map(|r| {
+            time(r).map(|t| {
+                let mut run = state::id_to_json(&state::id(&r.run));
+                run.insert(String::from("time"), json!(t.as_secs_f64()));
+                Value::from(run)
+            })
+        })
+        .collect::<Vec<_>>();
+    let error = |e: std::io::Error| {
+        Error::new(format!(
+            "Could not save baseline \"{name}\" to {}: {e}",
+            file.display()
+        ))
+    };
+    if let Some(d) = file.parent() {
+        fs::create_dir_all(d).map_err(error)?;
+    }
+    fs::write(&file, format!("{:#}\n", Value::from(runs))).map_err(error)
+}
+
+/// A saved baseline
+pub struct Baseline {
+    /// The name of the baseline
+    pub name: String,
+    /// The time taken by each run
+    times: Vec<(RunId, Duration)>,
+}
+
+/// Load a saved baseline
+pub fn load(dir: &Path, name: &str) -> Result<Baseline, Error> {
+    let file = baseline_file(dir, name)?;
+    let contents = fs::read_to_string(&file).map_err(|_| {
+        Error::new(format!(
+            "Could not find baseline \"{name}\": save it using --save-baseline {name}"
+        ))
+    })?;
+    let error = || Error::new(format!("Could not read baseline from {}", file.display()));
+    let mut times = vec![];
+    for r in serde_json::from_str::<Value>(&contents)
+        .map_err(|_| error())?
+        .as_array()
+        .ok_or_else(error)?
+    {
+        match (state::id_from_json(r), r["time"].as_f64()) {
+            (Some(id), Some(t)) if t >= 0.0 => times.push((id, Duration::from_secs_f64(t))),
+            _ => {
+                return Err(error());
+            }
+        }
+    }
+    Ok(Baseline {
+        name: name.to_string(),
+        times,
+    })
+}
+
+/// A run whose time changed by more than the threshold
+pub struct Change {
+    /// The example and template bindings of the run
+    pub label: String,
+    /// The time in the baseline
+    pub old: Duration,
+    /// The new time
+    pub new: Duration,
+}
+
+impl Change {
+    /// The relative change in time
+    pub fn ratio(&self) -> f64 {
+        self.new.as_secs_f64() / self.old.as_secs_f64() - 1.0
+    }
+
+    /// Is the run slower than in the baseline?
+    pub fn is_regression(&self) -> bool {
+        self.new > self.old
+    }
+}
+
+impl Baseline {
+    /// Find the runs whose time changed by more than `threshold` compared to the baseline
+    pub fn compare(&self, results: &[RunResult], threshold: f64) -> Vec<Change> {
+        let mut changes = vec![];
+        for r in results {
+            let id = state::id(&r.run);
+            if let Some(new) = time(r)
+                && let Some((_, old)) = self.times.iter().find(|(i, _)| *i == id)
+                && !old.is_zero()
+            {
+                let change = Change {
+                    label: label(&id),
+                    old: *old,
+                    new,
+                };
+                if change.ratio().abs() > threshold {
+                    changes.push(change);
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// Print the runs whose time changed compared to a baseline
+pub fn print_changes(name: &str, changes: &[Change]) {
+    println!();
+    println!("COMPARISON WITH BASELINE \"{name}\"");
+    if changes.is_empty() {
+        println!("No significant changes.");
+    }
+    for c in changes {
+        println!(
+            "{:>8} {:>9} -> {:>9}  {} {}",
+            format!("{:+.1}%", c.ratio() * 100.0),
+            format_duration(c.old),
+            format_duration(c.new),
+            if c.is_regression() {
+                "slower:"
+            } else {
+                "faster:"
+            },
+            c.label
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CargoCommand, Usage, plan::Run};
+
+    fn result(n: &str, wall: u64) -> RunResult {
+        RunResult {
+            run: Run {
+                dir: PathBuf::from("/crate"),
+                example: String::from("example"),
+                file: PathBuf::from("/crate/examples/example.rs"),
+                bindings: vec![(String::from("N"), String::from(n))],
+                command: CargoCommand::from_str("run --release", "example", None).unwrap(),
+                feature_set: None,
+                retries: 0,
+            },
+            outcome: Outcome::Passed,
+            usage: Some(Usage {
+                wall: Duration::from_millis(wall),
+                ..Usage::default()
+            }),
+            samples: vec![Duration::from_millis(wall)],
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = Baseline {
+            name: String::from("main"),
+            times: [("1", 100), ("2", 100), ("3", 100)]
+                .iter()
+                .map(|(n, t)| (state::id(&result(n, 0).run), Duration::from_millis(*t)))
+                .collect::<Vec<_>>(),
+        };
+        let changes = baseline.compare(
+            &[
+                result("1", 105),
+                result("2", 150),
+                result("3", 50),
+                result("4", 1000),
+            ],
+            parse_threshold("10%").unwrap(),
+        );
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].label, "example (N=2, release)");
+        assert!(changes[0].is_regression());
+        assert!((changes[0].ratio() - 0.5).abs() < 1e-9);
+        assert!(!changes[1].is_regression());
+
+        // Only the attempt of a flaky run that succeeded is compared
+        let mut flaky = result("1", 300);
+        flaky.outcome = Outcome::Flaky;
+        flaky.samples = vec![Duration::from_millis(100)];
+        assert!(baseline.compare(&[flaky], 0.1).is_empty());
+
+        // Runs for different targets are compared separately
+        let on_target = |wall| {
+            let mut r = result("2", wall);
+            r.run.command.set_target("wasm32-wasip1");
+            r
+        };
+        assert!(baseline.compare(&[on_target(150)], 0.1).is_empty());
+        let baseline = Baseline {
+            name: String::from("main"),
+            times: vec![(state::id(&on_target(0).run), Duration::from_millis(100))],
+        };
+        let changes = baseline.compare(&[result("2", 100), on_target(150)], 0.1);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].label, "example (N=2, release, wasm32-wasip1)");
+    }
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(parse_threshold("10%").unwrap(), 0.1);
+        assert_eq!(parse_threshold("25").unwrap(), 0.25);
+        assert!(parse_threshold("fast").is_err());
+        assert!(check_name("main").is_ok());
+        assert!(check_name("../main").is_err());
+    }
+}
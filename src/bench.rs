@@ -0,0 +1,190 @@
+//! Statistics of the wall-clock times of repeated runs
+
+use crate::{RunResult, error::Error, usage::format_duration};
+use serde_json::{Value, json};
+use std::{fs, time::Duration};
+
+/// Statistics of a set of wall-clock times
+#[derive(Debug, PartialEq)]
+pub struct Statistics {
+    /// The number of times
+    pub count: usize,
+    /// The shortest time
+    pub min: Duration,
+    /// The median time
+    pub median: Duration,
+    /// The mean time
+    pub mean: Duration,
+    /// The sample standard deviation of the times
+    pub stddev: Duration,
+}
+
+impl Statistics {
+    /// Compute the statistics of some times
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+        } else {
+            sorted[n / 2]
+        };
+        let mean = sorted.iter().map(Duration::as_secs_f64).sum::<f64>() / n as f64;
+        let variance = if n > 1 {
+            sorted
+                .iter()
+                .map(|s| (s.as_secs_f64() - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1) as f64
+        } else {
+            0.0
+        };
+        Some(Self {
+            count: n,
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
+
+/// The statistics of each run, labelled by its command and feature set
+pub fn run_rows(results: &[RunResult]) -> Vec<(String, Statistics)> {
+    results
+        .iter()
+        .filter_map(|r| Statistics::from_samples(&r.samples).map(|s| (r.run.description(), s)))
+        .collect::<Vec<_>>()
+}
+
+/// The statistics of all the runs that use each value of each template argument
+pub fn binding_rows(results: &[RunResult]) -> Vec<(String, Statistics)> {
+    let mut bindings: Vec<(String, Vec<Duration>)> = vec![];
+    for r in results {
+        for (name, value) in &r.run.bindings {
+            let label = format!("{name}={value}");
+            if let Some((_, samples)) = bindings.iter_mut().find(|(l, _)| *l == label) {
+                samples.extend_from_slice(&r.samples);
+            } else {
+                bindings.push((label, r.samples.clone()));
+            }
+        }
+    }
+    bindings
+        .into_iter()
+        .filter_map(|(label, samples)| Statistics::from_samples(&samples).map(|s| (label, s)))
+        .collect::<Vec<_>>()
+}
+
+/// Print a table of statistics
+pub fn print_table(title: &str, rows: &[(String, Statistics)]) {
+    if rows.is_empty() {
+        return;
+    }
+    println!();
+    println!("{title}");
+    println!(
+        "{:>5} {:>9} {:>9} {:>9} {:>9}  label",
+        "runs", "min", "median", "mean", "stddev"
+    );
+    for (label, s) in rows {
+        println!(
+            "{:>5} {:>9} {:>9} {:>9} {:>9}  {label}",
+            s.count,
+            format_duration(s.min),
+            format_duration(s.median),
+            format_duration(s.mean),
+            format_duration(s.stddev)
+        );
+    }
+}
+
+/// Convert tables of statistics to CSV, with times in seconds
+pub fn to_csv(runs: &[(String, Statistics)], bindings: &[(String, Statistics)]) -> String {
+    let mut csv = String::from("kind,label,runs,min,median,mean,stddev\n");
+    for (kind, rows) in [("run", runs), ("binding", bindings)] {
+        for (label, s) in rows {
+            csv.push_str(&format!(
+                "{kind},\"{}\",{},{},{},{},{}\n",
+                label.replace('"', "\"\""),
+                s.count,
+                s.min.as_secs_f64(),
+                s.median.as_secs_f64(),
+                s.mean.as_secs_f64(),
+                s.stddev.as_secs_f64()
+            ));
+        }
+    }
+    csv
+}
+
+/// Convert tables of statistics to JSON, with times in seconds
+pub fn to_json(runs: &[(String, Statistics)], bindings: &[(String, Statistics)]) -> Value {
+    let rows = |rows: &[(String, Statistics)]| {
+        rows.iter()
+            .map(|(label, s)| {
+                json!({
+                    "label": label,
+                    "runs": s.count,
+                    "min": s.min.as_secs_f64(),
+                    "median": s.median.as_secs_f64(),
+                    "mean": s.mean.as_secs_f64(),
+                    "stddev": s.stddev.as_secs_f64(),
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    json!({
+        "runs": rows(runs),
+        "bindings": rows(bindings),
+    })
+}
+
+/// Write an exported table to a file
+pub fn export(path: &str, contents: &str) -> Result<(), Error> {
+    fs::write(path, contents)
+        .map_err(|e| Error::new(format!("Could not export benchmark results to {path}: {e}")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let s = Statistics::from_samples(&[
+            Duration::from_secs(4),
+            Duration::from_secs(1),
+            Duration::from_secs(3),
+            Duration::from_secs(2),
+        ])
+        .unwrap();
+        assert_eq!(s.count, 4);
+        assert_eq!(s.min, Duration::from_secs(1));
+        assert_eq!(s.median, Duration::from_millis(2500));
+        assert_eq!(s.mean, Duration::from_millis(2500));
+        assert!((s.stddev.as_secs_f64() - 1.290994).abs() < 1e-6);
+
+        let s = Statistics::from_samples(&[Duration::from_secs(1)]).unwrap();
+        assert_eq!(s.stddev, Duration::ZERO);
+        assert!(Statistics::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let s = || Statistics::from_samples(&[Duration::from_secs(1)]).unwrap();
+        assert_eq!(
+            to_csv(
+                &[(String::from("cargo run --features \"one\""), s())],
+                &[(String::from("N=2"), s())]
+            ),
+            "kind,label,runs,min,median,mean,stddev\n\
+             run,\"cargo run --features \"\"one\"\"\",1,1,1,1,0\n\
+             binding,\"N=2\",1,1,1,1,0\n"
+        );
+    }
+}
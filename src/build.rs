@@ -0,0 +1,168 @@
+//! Functions to build examples before running them
+
+use crate::{error::Error, shell};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::Stdio,
+};
+
+/// The result of building a group of examples
+pub struct Build {
+    /// Whether the build was successful
+    pub success: bool,
+    /// The paths to the binaries of the examples that were built
+    pub executables: BTreeMap<String, PathBuf>,
+    /// A summary of the first error reported by the compiler
+    pub first_error: Option<String>,
+    /// A summary of the first error reported by the compiler for each target
+    pub errors: BTreeMap<String, String>,
+}
+
+/// Summarise a compiler diagnostic in a single line
+fn summarise(diagnostic: &Value) -> String {
+    let level = diagnostic["level"].as_str().unwrap_or("error");
+    let message = diagnostic["message"].as_str().unwrap_or_default();
+    let mut summary = match diagnostic["code"]["code"].as_str() {
+        Some(code) => format!("{level}[{code}]: {message}"),
+        None => format!("{level}: {message}"),
+    };
+    if let Some(spans) = diagnostic["spans"].as_array()
+        && let Some(span) = spans.iter().find(|s| s["is_primary"] == true)
+    {
+        summary.push_str(&format!(
+            " at {}:{}:{}",
+            span["file_name"].as_str().unwrap_or_default(),
+            span["line_start"],
+            span["column_start"]
+        ));
+    }
+    summary
+}
+
+impl Build {
+    /// Create new
+    fn new() -> Self {
+        Self {
+            success: false,
+            executables: BTreeMap::new(),
+            first_error: None,
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Read a line of cargo's JSON output, returning any compiler diagnostic that should be shown
+    fn read_message(&mut self, line: &str) -> Option<String> {
+        let Ok(message) = serde_json::from_str::<Value>(line) else {
+            return Some(format!("{line}\n"));
+        };
+        match message["reason"].as_str() {
+            Some("compiler-artifact") => {
+                if let Some(kinds) = message["target"]["kind"].as_array()
+                    && kinds.iter().any(|k| k == "example")
+                    && let Some(name) = message["target"]["name"].as_str()
+                    && let Some(executable) = message["executable"].as_str()
+                {
+                    self.executables
+                        .insert(name.to_string(), PathBuf::from(executable));
+                }
+                None
+            }
+            Some("compiler-message") => {
+                let diagnostic = &message["message"];
+                if diagnostic["level"] == "error" {
+                    let summary = summarise(diagnostic);
+                    if let Some(name) = message["target"]["name"].as_str() {
+                        self.errors
+                            .entry(name.to_string())
+                            .or_insert_with(|| summary.clone());
+                    }
+                    self.first_error.get_or_insert(summary);
+                }
+                diagnostic["rendered"].as_str().map(String::from)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Build examples using a `cargo build` command
+///
+/// The build keeps going after an example fails to build, so that the other examples are still
+/// built. Cargo's JSON output is used to find the binaries that were built and the first compiler
+/// error for each target. Compiler diagnostics are shown as they are received.
+pub fn build(command: &str) -> Result<Build, Error> {
+    let mut child = shell(&format!("{command} --keep-going --message-format=json"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::new(format!("Error initialising build: {e}")))?;
+    let mut build = Build::new();
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let line = line.map_err(|e| Error::new(format!("Error reading build output: {e}")))?;
+            if let Some(diagnostic) = build.read_message(&line) {
+                eprint!("{diagnostic}");
+            }
+        }
+    }
+    build.success = child
+        .wait()
+        .map_err(|e| Error::new(format!("Error building examples: {e}")))?
+        .success();
+    Ok(build)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_message() {
+        let mut build = Build::new();
+        assert!(
+            build
+                .read_message(r#"{"reason":"compiler-artifact","target":{"kind":["lib"],"name":"example1"},"executable":null}"#)
+                .is_none()
+        );
+        assert!(
+            build
+                .read_message(r#"{"reason":"compiler-artifact","target":{"kind":["example"],"name":"debug"},"executable":"/target/debug/examples/debug"}"#)
+                .is_none()
+        );
+        assert_eq!(build.executables.len(), 1);
+        assert_eq!(
+            build.executables.get("debug"),
+            Some(&PathBuf::from("/target/debug/examples/debug"))
+        );
+
+        let rendered = build.read_message(
+            r#"{"reason":"compiler-message","target":{"kind":["example"],"name":"broken"},"message":{"level":"error","message":"mismatched types","code":{"code":"E0308"},"spans":[{"file_name":"examples/debug.rs","line_start":2,"column_start":18,"is_primary":true}],"rendered":"error[E0308]: mismatched types\n"}}"#,
+        );
+        assert_eq!(
+            rendered.as_deref(),
+            Some("error[E0308]: mismatched types\n")
+        );
+        assert_eq!(
+            build.first_error.as_deref(),
+            Some("error[E0308]: mismatched types at examples/debug.rs:2:18")
+        );
+        build.read_message(
+            r#"{"reason":"compiler-message","target":{"kind":["example"],"name":"other"},"message":{"level":"error","message":"aborting","code":null,"spans":[],"rendered":"error: aborting\n"}}"#,
+        );
+        assert_eq!(
+            build.first_error.as_deref(),
+            Some("error[E0308]: mismatched types at examples/debug.rs:2:18")
+        );
+        assert_eq!(
+            build.errors.get("broken").map(String::as_str),
+            Some("error[E0308]: mismatched types at examples/debug.rs:2:18")
+        );
+        assert_eq!(
+            build.errors.get("other").map(String::as_str),
+            Some("error: aborting")
+        );
+        assert!(!build.errors.contains_key("debug"));
+    }
+}
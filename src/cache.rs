@@ -0,0 +1,293 @@
+//! A cache of successful runs, used to skip runs whose inputs have not changed
+
+use crate::{cargo_toml, error::Error, plan::Run};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A 64-bit FNV-1a hash
+///
+/// Unlike the hashers in the standard library, this gives the same hash for every version of Rust,
+/// so keys saved by one version can be compared with keys computed by another.
+struct StableHasher(u64);
+
+impl StableHasher {
+    /// Create new
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    /// Add bytes to the hash, preceded by their length so that consecutive values cannot run together
+    fn write(&mut self, bytes: &[u8]) {
+        for b in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Get the hash as a hexadecimal string
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Add the contents of a file, or of every file in a directory, to a hash
+fn hash_path(path: &Path, hasher: &mut StableHasher) {
+    if path.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            let mut entries = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for e in entries {
+                hash_path(&e, hasher);
+            }
+        }
+    } else if let Ok(contents) = fs::read(path) {
+        hasher.write(path.to_string_lossy().as_bytes());
+        hasher.write(&contents);
+    }
+}
+
+/// Get the version of the Rust toolchain
+fn toolchain_version() -> String {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default()
+}
+
+/// Get the environment variables that can affect how cargo builds and runs examples
+///
+/// These are the variables whose names start with `CARGO` or `RUST`, such as `RUSTFLAGS`.
+fn build_environment() -> Vec<(String, String)> {
+    let mut variables = env::vars()
+        .filter(|(name, _)| name.starts_with("CARGO") || name.starts_with("RUST"))
+        .collect::<Vec<_>>();
+    variables.sort();
+    variables
+}
+
+/// The inputs of the runs that were successful the last time they were run
+pub struct Cache {
+    /// The file that the cache is stored in
+    file: PathBuf,
+    /// The version of the Rust toolchain
+    toolchain: String,
+    /// The key of the inputs of the last successful run of each command
+    entries: BTreeMap<String, String>,
+    /// The key of the current inputs of each command that has been checked
+    current: BTreeMap<String, String>,
+    /// The key of the inputs shared by all the runs of each package, computed once per invocation
+    packages: BTreeMap<PathBuf, String>,
+}
+
+impl Cache {
+    /// Load the cache for the packages in a directory
+    ///
+    /// The cache is stored in the target directory. If it does not exist yet, the cache is empty.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let file = cargo_toml::join(
+            &cargo_toml::join(&cargo_toml::target_dir(&dir)?, "templated-examples"),
+            "incremental",
+        );
+        let mut entries = BTreeMap::new();
+        if let Ok(contents) = fs::read_to_string(&file) {
+            for line in contents.lines() {
+                if let Some((key, command)) = line.split_once(' ') {
+                    entries.insert(command.to_string(), key.to_string());
+                }
+            }
+        }
+        Ok(Self {
+            file,
+            toolchain: toolchain_version(),
+            entries,
+            current: BTreeMap::new(),
+            packages: BTreeMap::new(),
+        })
+    }
+
+    /// Compute a key from the inputs that are shared by all the runs of a package
+    ///
+    /// This includes the sources and manifests of the package and of its local path dependencies,
+    /// the lock file, the environment variables that affect cargo, and the version of the
+    /// toolchain. As these are the same for every run of the package, the key is only computed
+    /// once.
+    fn package_key(&mut self, dir: &Path) -> String {
+        if let Some(key) = self.packages.get(dir) {
+            return key.clone();
+        }
+        let mut hasher = StableHasher::new();
+        hasher.write(self.toolchain.as_bytes());
+        // Local path dependencies, including other members of the workspace, are part of the build
+        let mut packages = vec![PathBuf::from(dir)];
+        let mut i = 0;
+        while i < packages.len() {
+            for path in ["Cargo.toml", "build.rs", "src"] {
+                hash_path(&cargo_toml::join(&packages[i], path), &mut hasher);
+            }
+            for d in cargo_toml::load_path_dependencies(&packages[i]).unwrap_or_default() {
+                if !packages.contains(&d) {
+                    packages.push(d);
+                }
+            }
+            i += 1;
+        }
+        for (name, value) in build_environment() {
+            hasher.write(name.as_bytes());
+            hasher.write(value.as_bytes());
+        }
+        let root = cargo_toml::find_workspace_root(&dir)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| PathBuf::from(dir));
+        for path in ["Cargo.toml", "Cargo.lock"] {
+            hash_path(&cargo_toml::join(&root, path), &mut hasher);
+        }
+        let key = hasher.finish();
+        self.packages.insert(PathBuf::from(dir), key.clone());
+        key
+    }
+
+    /// Compute a key from everything that can affect the outcome of a run
+    ///
+    /// This includes the inputs shared by the runs of its package, the source of the example and
+    /// the command.
+    fn key(&mut self, run: &Run) -> String {
+        let mut hasher = StableHasher::new();
+        hasher.write(self.package_key(&run.dir).as_bytes());
+        hasher.write(run.command.as_string().as_bytes());
+        // Examples in their own directory (eg examples/foo/main.rs) can be split across several files
+        match run.file.parent() {
+            Some(d) if run.file.file_name().is_some_and(|n| n == "main.rs") => {
+                hash_path(d, &mut hasher);
+            }
+            _ => {
+                hash_path(&run.file, &mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Check whether the inputs of a run are unchanged since it last succeeded
+    pub fn is_fresh(&mut self, run: &Run) -> bool {
+        let command = run.command.as_string();
+        let key = self.key(run);
+        let fresh = self.entries.get(&command) == Some(&key);
+        self.current.insert(command, key);
+        fresh
+    }
+
+    /// Record that a run succeeded with the inputs it had when it was checked
+    pub fn record_success(&mut self, run: &Run) {
+        let command = run.command.as_string();
+        if let Some(key) = self.current.get(&command) {
+            self.entries.insert(command, key.clone());
+        }
+    }
+
+    /// Record that a run did not succeed, so that it will be run again next time
+    pub fn record_failure(&mut self, run: &Run) {
+        self.entries.remove(&run.command.as_string());
+    }
+
+    /// Save the cache
+    pub fn save(&self) -> Result<(), Error> {
+        let error = |e: std::io::Error| {
+            Error::new(format!(
+                "Could not save incremental cache to {}: {e}",
+                self.file.display()
+            ))
+        };
+        if let Some(d) = self.file.parent() {
+            fs::create_dir_all(d).map_err(error)?;
+        }
+        let mut contents = String::new();
+        for (command, key) in &self.entries {
+            contents.push_str(&format!("{key} {command}\n"));
+        }
+        fs::write(&self.file, contents).map_err(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CargoCommand, temp_dir::TempDir};
+
+    #[test]
+    fn test_is_fresh() {
+        let dir = TempDir::new();
+        dir.write("example.rs", "fn main() {}");
+        let file = dir.join("example.rs");
+        let run = Run {
+            dir: dir.path().to_path_buf(),
+            example: String::from("example"),
+            file: file.clone(),
+            bindings: vec![],
+            command: CargoCommand::from_str("run --release", "example", None).unwrap(),
+            feature_set: None,
+            retries: 0,
+        };
+        // The inputs shared by the runs of a package are read again by each new invocation
+        let reload = |cache: Cache| Cache {
+            current: BTreeMap::new(),
+            packages: BTreeMap::new(),
+            ..cache
+        };
+        let mut cache = Cache {
+            file: dir.join("incremental"),
+            toolchain: String::from("rustc 1.0.0"),
+            entries: BTreeMap::new(),
+            current: BTreeMap::new(),
+            packages: BTreeMap::new(),
+        };
+
+        assert!(!cache.is_fresh(&run));
+        cache.record_success(&run);
+        assert!(cache.is_fresh(&run));
+        dir.write("example.rs", "fn main() { println!(\"changed\"); }");
+        assert!(!cache.is_fresh(&run));
+        cache.record_success(&run);
+        assert!(cache.is_fresh(&run));
+        cache.record_failure(&run);
+        assert!(!cache.is_fresh(&run));
+
+        // Changes to a local path dependency are also inputs of the run
+        dir.write(
+            "Cargo.toml",
+            "[package]\nname = \"example\"\n\n[dependencies]\ndep = { path = \"dep\" }\n",
+        );
+        dir.write("dep/Cargo.toml", "[package]\nname = \"dep\"\n");
+        dir.write("dep/src/lib.rs", "");
+        let mut cache = reload(cache);
+        assert!(!cache.is_fresh(&run));
+        cache.record_success(&run);
+        assert!(cache.is_fresh(&run));
+        dir.write("dep/src/lib.rs", "pub fn f() {}");
+        let mut cache = reload(cache);
+        assert!(!cache.is_fresh(&run));
+        assert_eq!(cache.packages.len(), 1);
+    }
+
+    #[test]
+    fn test_stable_hasher() {
+        // Keys are saved, so the hash must not change between versions of Rust
+        let mut hasher = StableHasher::new();
+        hasher.write(b"rustc 1.0.0");
+        assert_eq!(hasher.finish(), "38fc327dac4f8502");
+        let mut hasher = StableHasher::new();
+        hasher.write(b"ab");
+        hasher.write(b"c");
+        let mut other = StableHasher::new();
+        other.write(b"a");
+        other.write(b"bc");
+        assert_ne!(hasher.finish(), other.finish());
+    }
+}
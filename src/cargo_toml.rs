@@ -1,13 +1,237 @@
 //! Functions to read information from Cargo.toml
 
-use crate::{BuildType, CargoCommand};
-use cargo_toml::Manifest;
+use crate::{
+    BuildType, CargoCommand,
+    error::{Error, Location},
+    parsing::glob_match,
+};
+use cargo_toml::{Dependency, Manifest, Value};
 use std::{
-    collections::HashMap,
-    env::current_dir,
+    collections::BTreeMap,
+    env::{self, current_dir},
     fs,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
+use toml::de::{DeTable, DeValue};
+
+/// Keys in the templated-examples metadata that are options rather than template arguments
+const OPTIONS: [&str; 9] = [
+    "build",
+    "no-default-features",
+    "all-features",
+    "feature-sets",
+    "exclude-features",
+    "mutually-exclusive-features",
+    "target",
+    "build-only-targets",
+    "retries",
+];
+
+/// Keys in the templated-examples metadata of an example
+const EXAMPLE_OPTIONS: [&str; 7] = [
+    "command",
+    "build",
+    "no-default-features",
+    "all-features",
+    "feature-sets",
+    "target",
+    "retries",
+];
+
+/// The name of the configuration file
+const CONFIG_FILE: &str = "templated-examples.toml";
+
+/// Profiles that are defined by cargo
+const BUILT_IN_PROFILES: [&str; 4] = ["dev", "release", "test", "bench"];
+
+/// A value read from a TOML file, and the keys of the value in the file
+#[derive(Clone, Debug)]
+struct Entry {
+    value: Value,
+    file: PathBuf,
+    keys: Vec<String>,
+}
+
+impl Entry {
+    /// Create an error pointing at this value
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::new(message).at(self.location())
+    }
+
+    /// The location of this value
+    fn location(&self) -> Option<Location> {
+        locate(&self.file, &self.keys)
+    }
+
+    /// The full name of the key of this value
+    fn name(&self) -> String {
+        self.keys.join(".")
+    }
+
+    /// Get a value from a table
+    fn get(&self, key: &str) -> Option<Entry> {
+        self.value.get(key).map(|v| {
+            let mut keys = self.keys.clone();
+            keys.push(String::from(key));
+            Entry {
+                value: v.clone(),
+                file: self.file.clone(),
+                keys,
+            }
+        })
+    }
+
+    /// Get the entries of a table
+    fn entries(&self) -> Result<Vec<Entry>, Error> {
+        Ok(self
+            .value
+            .as_table()
+            .ok_or_else(|| self.error(format!("{} must be a table", self.name())))?
+            .keys()
+            .filter_map(|k| self.get(k))
+            .collect::<Vec<_>>())
+    }
+
+    /// Get the last key of this value
+    fn key(&self) -> &str {
+        self.keys.last().map_or("", |k| k.as_str())
+    }
+
+    /// Parse a string
+    fn as_str(&self) -> Result<&str, Error> {
+        self.value
+            .as_str()
+            .ok_or_else(|| self.error(format!("{} must be a string", self.name())))
+    }
+
+    /// Parse a boolean
+    fn as_bool(&self) -> Result<bool, Error> {
+        self.value
+            .as_bool()
+            .ok_or_else(|| self.error(format!("{} must be a boolean", self.name())))
+    }
+
+    /// Parse a non-negative integer
+    fn as_usize(&self) -> Result<usize, Error> {
+        self.value
+            .as_integer()
+            .and_then(|i| usize::try_from(i).ok())
+            .ok_or_else(|| self.error(format!("{} must be a non-negative integer", self.name())))
+    }
+
+    /// Parse an array of strings
+    fn as_string_array(&self) -> Result<Vec<String>, Error> {
+        let error = || self.error(format!("{} must be an array of strings", self.name()));
+        self.value
+            .as_array()
+            .ok_or_else(error)?
+            .iter()
+            .map(|v| v.as_str().map(String::from).ok_or_else(error))
+            .collect()
+    }
+
+    /// Get the elements of an array
+    fn elements(&self) -> Option<Vec<Entry>> {
+        Some(
+            self.value
+                .as_array()?
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let mut keys = self.keys.clone();
+                    keys.push(i.to_string());
+                    Entry {
+                        value: v.clone(),
+                        file: self.file.clone(),
+                        keys,
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Parse an array of features, checking that every feature is available in the package
+    fn as_features(&self, available: &[String]) -> Result<Vec<String>, Error> {
+        let features = self.as_string_array()?;
+        for (f, e) in features.iter().zip(self.elements().unwrap_or_default()) {
+            if !available.contains(f) {
+                return Err(e.error(format!("Unknown feature \"{f}\" in {}", self.name())));
+            }
+        }
+        Ok(features)
+    }
+
+    /// Parse an array of arrays of features, checking that every feature is available in the package
+    fn as_feature_lists(&self, available: &[String]) -> Result<Vec<Vec<String>>, Error> {
+        let error = || {
+            self.error(format!(
+                "{} must be an array of arrays of strings",
+                self.name()
+            ))
+        };
+        self.elements()
+            .ok_or_else(error)?
+            .iter()
+            .map(|e| {
+                if e.value.is_array() {
+                    e.as_features(available)
+                } else {
+                    Err(error())
+                }
+            })
+            .collect()
+    }
+
+    /// Parse an array of feature sets
+    fn as_feature_sets(&self, available: &[String]) -> Result<Vec<Vec<String>>, Error> {
+        let feature_sets = self.as_feature_lists(available)?;
+        if feature_sets.is_empty() {
+            return Err(self.error(format!(
+                "{} must contain at least one feature set",
+                self.name()
+            )));
+        }
+        Ok(feature_sets)
+    }
+
+    /// Parse a build type or an array of build types, checking that every profile is defined
+    fn as_build_types(&self, profiles: &[String]) -> Result<Vec<BuildType>, Error> {
+        let builds = self.as_string_list()?;
+        if builds.is_empty() {
+            return Err(self.error(format!(
+                "{} must contain at least one build type",
+                self.name()
+            )));
+        }
+        let entries = self.elements().unwrap_or_else(|| vec![self.clone()]);
+        builds
+            .iter()
+            .zip(entries)
+            .map(|(b, e)| {
+                let build = BuildType::from_str(b);
+                if build.is_defined(profiles) {
+                    Ok(build)
+                } else {
+                    Err(e.error(format!("Profile \"{b}\" in {} is not defined", self.name())))
+                }
+            })
+            .collect()
+    }
+
+    /// Parse a value that can be either a string or an array of strings
+    fn as_string_list(&self) -> Result<Vec<String>, Error> {
+        if let Some(s) = self.value.as_str() {
+            Ok(vec![String::from(s)])
+        } else {
+            self.as_string_array().map_err(|_| {
+                self.error(format!(
+                    "{} must be a string or an array of strings",
+                    self.name()
+                ))
+            })
+        }
+    }
+}
 
 /// Join a directory and a file name
 pub fn join(part1: &impl AsRef<Path>, part2: &str) -> PathBuf {
@@ -17,117 +241,971 @@ pub fn join(part1: &impl AsRef<Path>, part2: &str) -> PathBuf {
 }
 
 /// Find directory containing Cargo.toml
-pub fn find() -> PathBuf {
-    let mut dir = current_dir().expect("Cannot find current dir");
+pub fn find() -> Result<PathBuf, Error> {
+    let mut dir =
+        current_dir().map_err(|e| Error::new(format!("Cannot find current directory: {e}")))?;
     while !join(&dir, "Cargo.toml").exists() {
-        dir = dir.parent().expect("Cannot find Cargo.toml").to_path_buf();
+        dir = dir
+            .parent()
+            .ok_or_else(|| {
+                Error::new(
+                    "Could not find Cargo.toml in the current directory or any parent directory",
+                )
+            })?
+            .to_path_buf();
     }
-    dir
+    Ok(dir)
 }
 
-/// Load Cargo.toml
-fn cargo_toml(dir: &impl AsRef<Path>) -> Manifest {
-    Manifest::from_str(
-        &fs::read_to_string(join(dir, "Cargo.toml")).expect("Cannot read Cargo.toml"),
-    )
-    .expect("Could not parse Cargo.toml")
-}
-
-/// Load template arguments from the package.metadata.templated-examples section of Cargo.toml
-pub fn load_args(dir: &impl AsRef<Path>, args: &mut HashMap<String, Vec<String>>) {
-    if let Some(p) = cargo_toml(dir).package
-        && let Some(m) = p.metadata
-        && let Some(e) = m.get("templated-examples")
+/// Find the location of a value in a TOML file
+fn locate(file: &Path, keys: &[String]) -> Option<Location> {
+    let contents = fs::read_to_string(file).ok()?;
+    let root = DeValue::Table(DeTable::parse(&contents).ok()?.into_inner());
+    let mut value = &root;
+    let mut span = 0..0;
+    for k in keys {
+        let v = match k.parse::<usize>() {
+            Ok(i) if value.is_array() => value.get(i)?,
+            _ => value.get(k)?,
+        };
+        span = v.span();
+        value = v.get_ref();
+    }
+    Some(Location::from_span(file, &contents, span))
+}
+
+/// Convert an error from parsing a TOML file
+fn parse_error(file: &Path, contents: &str, e: &toml::de::Error) -> Error {
+    let message = format!("Could not parse {}: {}", file.display(), e.message());
+    match e.span() {
+        Some(span) => Error::new(message).at(Some(Location::from_span(file, contents, span))),
+        None => Error::new(message),
+    }
+}
+
+/// Convert an error from the cargo_toml crate
+fn manifest_error(file: &Path, e: cargo_toml::Error) -> Error {
+    if let cargo_toml::Error::Parse(p) = &e
+        && let Ok(contents) = fs::read_to_string(file)
     {
-        for (i, j) in e
-            .as_table()
-            .expect("Could not parse package.metadata.templated-examples")
-        {
-            if i != "build" {
-                args.insert(
-                    i.clone(),
-                    j.as_array()
-                        .expect("Values in package.metadata.templated-examples must be arrays of strings")
-                        .iter()
-                        .map(|value| String::from(value.as_str()
-                        .expect("Values in package.metadata.templated-examples must be arrays of strings")))
-                        .collect::<Vec<_>>(),
-                );
+        parse_error(file, &contents, p)
+    } else {
+        Error::new(format!("Could not parse {}: {e}", file.display()))
+    }
+}
+
+/// Load Cargo.toml
+fn cargo_toml(dir: &impl AsRef<Path>) -> Result<Manifest, Error> {
+    let file = join(dir, "Cargo.toml");
+    let contents = fs::read_to_string(&file)
+        .map_err(|e| Error::new(format!("Cannot read {}: {e}", file.display())))?;
+    Manifest::from_str(&contents).map_err(|e| manifest_error(&file, e))
+}
+
+/// Find the configuration file for a package
+///
+/// This is the first templated-examples.toml found in the package's directory or its parents, up
+/// to the root of the workspace.
+fn find_config(dir: &impl AsRef<Path>) -> Result<Option<PathBuf>, Error> {
+    let dir = normalise(dir.as_ref());
+    let root = find_workspace_root(&dir)?
+        .filter(|r| dir.starts_with(r))
+        .unwrap_or_else(|| dir.clone());
+    for a in dir.ancestors() {
+        let file = join(&a, CONFIG_FILE);
+        if file.is_file() {
+            return Ok(Some(file));
+        }
+        if a == root {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Load the configuration file for a package, if there is one
+///
+/// If `file` is given (using --config), it is used instead of searching for the file.
+fn config(dir: &impl AsRef<Path>, file: Option<&Path>) -> Result<Option<Entry>, Error> {
+    let file = match file {
+        Some(f) => PathBuf::from(f),
+        None => match find_config(dir)? {
+            Some(f) => f,
+            None => {
+                return Ok(None);
             }
+        },
+    };
+    let contents = fs::read_to_string(&file)
+        .map_err(|e| Error::new(format!("Cannot read {}: {e}", file.display())))?;
+    let table =
+        toml::from_str::<toml::Table>(&contents).map_err(|e| parse_error(&file, &contents, &e))?;
+    Ok(Some(Entry {
+        value: Value::Table(table),
+        file,
+        keys: vec![],
+    }))
+}
+
+/// Get a section of the metadata of a package or workspace
+fn metadata_section(dir: &impl AsRef<Path>, keys: &[&str]) -> Result<Option<Entry>, Error> {
+    let manifest = cargo_toml(dir)?;
+    let metadata = match keys[0] {
+        "workspace" => manifest.workspace.and_then(|w| w.metadata),
+        _ => manifest.package.and_then(|p| p.metadata),
+    };
+    let mut entry = match metadata {
+        Some(m) => Entry {
+            value: m,
+            file: join(dir, "Cargo.toml"),
+            keys: vec![String::from(keys[0]), String::from("metadata")],
+        },
+        None => {
+            return Ok(None);
         }
+    };
+    for k in &keys[1..] {
+        entry = match entry.get(k) {
+            Some(e) => e,
+            None => {
+                return Ok(None);
+            }
+        };
     }
+    Ok(Some(entry))
 }
 
-/// Get default build type
-pub fn get_default_build(dir: &impl AsRef<Path>) -> BuildType {
-    if let Some(p) = cargo_toml(dir).package
-        && let Some(m) = p.metadata
-        && let Some(e) = m.get("templated-examples")
+/// Find the root directory of the workspace that a package belongs to
+pub fn find_workspace_root(dir: &impl AsRef<Path>) -> Result<Option<PathBuf>, Error> {
+    let dir = normalise(dir.as_ref());
+    let manifest = cargo_toml(&dir)?;
+    if manifest.workspace.is_some() {
+        return Ok(Some(dir));
+    }
+    if let Some(p) = manifest.package
+        && let Some(w) = p.workspace
     {
-        for (i, j) in e
-            .as_table()
-            .expect("Could not parse package.metadata.templated-examples")
+        return Ok(Some(normalise(&dir.join(w))));
+    }
+    for a in dir.ancestors().skip(1) {
+        if join(&a, "Cargo.toml").is_file()
+            && let Some(members) = get_workspace(&a)?
         {
-            if i == "build" {
-                return BuildType::from_str(j.as_str().expect("Build type must be a string"));
+            return Ok(if members.contains(&dir) {
+                Some(PathBuf::from(a))
+            } else {
+                None
+            });
+        }
+    }
+    Ok(None)
+}
+
+/// The templated-examples options and template arguments of a package
+///
+/// These are read once for each package from the configuration file and the Cargo.toml files of
+/// the package and its workspace.
+pub struct Metadata {
+    /// The directory containing the package's Cargo.toml
+    dir: PathBuf,
+    /// The configuration file, if there is one
+    config: Option<Entry>,
+    /// The crate-wide options and template arguments
+    table: BTreeMap<String, Entry>,
+    /// The names of the presets defined for the package
+    presets: Vec<String>,
+}
+
+impl Metadata {
+    /// Load the metadata of a package
+    ///
+    /// `config` is the configuration file passed using --config and `preset` is the preset passed
+    /// using --preset. Crate-wide values set for the package override those set for the workspace,
+    /// which override those set in the configuration file. Values set in the preset's section of
+    /// any of these override all other crate-wide values.
+    pub fn load(
+        dir: &impl AsRef<Path>,
+        config: Option<&Path>,
+        preset: Option<&str>,
+    ) -> Result<Self, Error> {
+        let config = self::config(dir, config)?;
+        let mut sections = vec![];
+        if let Some(c) = &config {
+            sections.push(c.clone());
+        }
+        if let Some(root) = find_workspace_root(dir)?
+            && let Some(e) = metadata_section(&root, &["workspace", "templated-examples"])?
+        {
+            sections.push(e);
+        }
+        if let Some(e) = metadata_section(dir, &["package", "templated-examples"])? {
+            sections.push(e);
+        }
+
+        let mut table = BTreeMap::new();
+        let mut presets = vec![];
+        for s in &sections {
+            for entry in s.entries()? {
+                // The example section of the configuration file sets options for individual
+                // examples
+                if entry.key() == "presets" {
+                    for p in entry.entries()? {
+                        if !presets.iter().any(|n| n == p.key()) {
+                            presets.push(String::from(p.key()));
+                        }
+                    }
+                } else if entry.key() != "example" || !s.keys.is_empty() {
+                    table.insert(String::from(entry.key()), entry);
+                }
+            }
+        }
+        if let Some(name) = preset {
+            for s in &sections {
+                if let Some(p) = s.get("presets").and_then(|p| p.get(name)) {
+                    for entry in p.entries()? {
+                        table.insert(String::from(entry.key()), entry);
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            dir: PathBuf::from(dir.as_ref()),
+            config,
+            table,
+            presets,
+        })
+    }
+
+    /// Get the names of the presets defined for the package
+    pub fn presets(&self) -> &[String] {
+        &self.presets
+    }
+
+    /// Remove the crate-wide values that are invalid, returning an error for each of them
+    ///
+    /// This allows the rest of the package to be loaded as if the invalid values were not set, so
+    /// that all problems can be reported at once. Keys that are not options must be template
+    /// arguments, so their values must be arrays of strings.
+    pub fn remove_invalid_values(&mut self) -> Vec<Error> {
+        let mut errors = vec![];
+        let keys = self.table.keys().cloned().collect::<Vec<_>>();
+        for key in keys {
+            let result = match key.as_str() {
+                "build" => get_default_builds(self).map(|_| ()),
+                "no-default-features" | "all-features" => get_default_flag(self, &key).map(|_| ()),
+                "feature-sets" => get_default_feature_sets(self).map(|_| ()),
+                "exclude-features" => get_excluded_features(self).map(|_| ()),
+                "mutually-exclusive-features" => get_mutually_exclusive_features(self).map(|_| ()),
+                "target" | "build-only-targets" => get_default_list(self, &key).map(|_| ()),
+                "retries" => get_default_number(self, &key).map(|_| ()),
+                _ => {
+                    let entry = &self.table[&key];
+                    entry.as_string_array().map(|_| ()).map_err(|_| {
+                        entry.error(format!(
+                            "Unknown option or invalid template argument \"{key}\": template arguments must be arrays of strings"
+                        ))
+                    })
+                }
+            };
+            if let Err(e) = result {
+                errors.push(e);
+                self.table.remove(&key);
+            }
+        }
+        errors
+    }
+
+    /// Get a crate-wide option or template argument
+    fn get(&self, key: &str) -> Option<&Entry> {
+        self.table.get(key)
+    }
+
+    /// Get the sections that set options for an example, in order of precedence
+    ///
+    /// These are the [package.metadata.example.{{eg}}.templated-examples] section of Cargo.toml
+    /// and the [example.{{eg}}] section of the configuration file.
+    fn example_sections(&self, eg: &str) -> Result<Vec<Entry>, Error> {
+        let mut sections = vec![];
+        if let Some(d) =
+            metadata_section(&self.dir, &["package", "example", eg, "templated-examples"])?
+        {
+            sections.push(d);
+        }
+        if let Some(c) = &self.config
+            && let Some(d) = c.get("example").and_then(|e| e.get(eg))
+        {
+            sections.push(d);
+        }
+        Ok(sections)
+    }
+
+    /// Get an option set for an example
+    fn example_option(&self, eg: &str, key: &str) -> Result<Option<Entry>, Error> {
+        Ok(self.example_sections(eg)?.iter().find_map(|d| d.get(key)))
+    }
+}
+
+/// Load template arguments from the templated-examples metadata
+pub fn load_args(
+    metadata: &Metadata,
+    args: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(), Error> {
+    for (i, j) in &metadata.table {
+        if !OPTIONS.contains(&i.as_str()) {
+            args.insert(i.clone(), j.as_string_array()?);
+        }
+    }
+    Ok(())
+}
+
+/// Load the locations where template arguments are given values in the templated-examples metadata
+pub fn load_arg_locations(metadata: &Metadata) -> BTreeMap<String, Option<Location>> {
+    metadata
+        .table
+        .iter()
+        .filter(|(i, _)| !OPTIONS.contains(&i.as_str()))
+        .map(|(i, j)| (i.clone(), j.location()))
+        .collect()
+}
+
+/// Get the default build types
+///
+/// If no build type is set, examples are run in release mode.
+pub fn get_default_builds(metadata: &Metadata) -> Result<Vec<BuildType>, Error> {
+    Ok(if let Some(b) = metadata.get("build") {
+        b.as_build_types(&load_profiles(&metadata.dir)?)?
+    } else {
+        vec![BuildType::Release]
+    })
+}
+
+/// Get the value of a boolean option in the templated-examples metadata
+pub fn get_default_flag(metadata: &Metadata, option: &str) -> Result<bool, Error> {
+    if let Some(f) = metadata.get(option) {
+        f.as_bool()
+    } else {
+        Ok(false)
+    }
+}
+
+/// Get the value of an integer option in the templated-examples metadata
+pub fn get_default_number(metadata: &Metadata, option: &str) -> Result<Option<usize>, Error> {
+    metadata.get(option).map(Entry::as_usize).transpose()
+}
+
+/// Get the value of a list option in the templated-examples metadata
+pub fn get_default_list(metadata: &Metadata, option: &str) -> Result<Vec<String>, Error> {
+    if let Some(t) = metadata.get(option) {
+        t.as_string_list()
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Get the crate-wide feature sets that each example is run with
+pub fn get_default_feature_sets(metadata: &Metadata) -> Result<Option<Vec<Vec<String>>>, Error> {
+    metadata
+        .get("feature-sets")
+        .map(|f| f.as_feature_sets(&load_available_features(&metadata.dir)?))
+        .transpose()
+}
+
+/// Get the features that are left out when generating combinations of features
+pub fn get_excluded_features(metadata: &Metadata) -> Result<Vec<String>, Error> {
+    if let Some(f) = metadata.get("exclude-features") {
+        f.as_features(&load_available_features(&metadata.dir)?)
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Get the groups of features that cannot be enabled together
+pub fn get_mutually_exclusive_features(metadata: &Metadata) -> Result<Vec<Vec<String>>, Error> {
+    if let Some(f) = metadata.get("mutually-exclusive-features") {
+        f.as_feature_lists(&load_available_features(&metadata.dir)?)
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Get the directories of the members of a workspace
+///
+/// Glob patterns in `workspace.members` are expanded and members in `workspace.exclude` are removed.
+pub fn get_workspace(dir: &impl AsRef<Path>) -> Result<Option<Vec<PathBuf>>, Error> {
+    let w = match cargo_toml(dir)?.workspace {
+        Some(w) => w,
+        None => {
+            return Ok(None);
+        }
+    };
+    let member_error = |n: usize, message: String| {
+        Error::new(message).at(locate(
+            &join(dir, "Cargo.toml"),
+            &[
+                String::from("workspace"),
+                String::from("members"),
+                n.to_string(),
+            ],
+        ))
+    };
+    let exclude = w
+        .exclude
+        .iter()
+        .map(|e| normalise(&join(dir, e)))
+        .collect::<Vec<_>>();
+    let mut members = vec![];
+    for (n, m) in w.members.iter().enumerate() {
+        let mut paths = vec![PathBuf::from(dir.as_ref())];
+        for component in m.split("/") {
+            let mut new_paths = vec![];
+            for p in &paths {
+                if component.contains(['*', '?']) {
+                    let mut matches = fs::read_dir(p)
+                        .map_err(|e| {
+                            member_error(n, format!("Could not read workspace member \"{m}\": {e}"))
+                        })?
+                        .filter_map(|d| d.ok().map(|d| d.path()))
+                        .filter(|d| {
+                            d.is_dir()
+                                && d.file_name()
+                                    .and_then(|n| n.to_str())
+                                    .is_some_and(|n| glob_match(component, n))
+                        })
+                        .collect::<Vec<_>>();
+                    matches.sort();
+                    new_paths.extend(matches);
+                } else {
+                    new_paths.push(join(p, component));
+                }
+            }
+            paths = new_paths;
+        }
+        for p in paths {
+            let p = normalise(&p);
+            if !join(&p, "Cargo.toml").is_file() {
+                if m.contains(['*', '?']) {
+                    continue;
+                }
+                return Err(member_error(
+                    n,
+                    format!("Could not find Cargo.toml for workspace member \"{m}\""),
+                ));
+            }
+            if !exclude.iter().any(|e| p.starts_with(e)) && !members.contains(&p) {
+                members.push(p);
             }
         }
     }
-    BuildType::Release
+    Ok(Some(members))
+}
+
+/// Remove `.` and `..` components from a path
+fn normalise(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Get the path of the Cargo.toml file in a directory, relative to the current directory if possible
+pub fn manifest_path(dir: &impl AsRef<Path>) -> PathBuf {
+    let path = join(dir, "Cargo.toml");
+    if let Ok(current) = current_dir()
+        && let Ok(p) = path.strip_prefix(current)
+    {
+        PathBuf::from(p)
+    } else {
+        path
+    }
+}
+
+/// Get the directory that cargo puts build outputs in for a package
+pub fn target_dir(dir: &impl AsRef<Path>) -> Result<PathBuf, Error> {
+    if let Some(d) = env::var_os("CARGO_TARGET_DIR") {
+        return Ok(PathBuf::from(d));
+    }
+    let root = find_workspace_root(dir)?.unwrap_or_else(|| PathBuf::from(dir.as_ref()));
+    Ok(join(&root, "target"))
+}
+
+/// Check whether cargo may use a runner to run the binaries that it builds
+///
+/// Runners are set using `CARGO_TARGET_<TRIPLE>_RUNNER` or in the `target` section of cargo's
+/// configuration files, which are found in the current directory, its parents and cargo's home.
+pub fn runner_configured() -> bool {
+    if env::vars_os().any(|(k, _)| {
+        k.to_str()
+            .is_some_and(|k| k.starts_with("CARGO_TARGET_") && k.ends_with("_RUNNER"))
+    }) {
+        return true;
+    }
+    let mut dirs = current_dir()
+        .map(|d| {
+            d.ancestors()
+                .map(|a| join(&a, ".cargo"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if let Some(d) = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|h| join(&h, ".cargo")))
+    {
+        dirs.push(d);
+    }
+    dirs.iter()
+        .flat_map(|d| [join(d, "config.toml"), join(d, "config")])
+        .filter_map(|f| fs::read_to_string(f).ok())
+        .filter_map(|c| toml::from_str::<toml::Table>(&c).ok())
+        .any(|c| {
+            c.get("target")
+                .and_then(|t| t.as_table())
+                .is_some_and(|t| t.values().any(|v| v.get("runner").is_some()))
+        })
 }
 
-/// Get default build type
-pub fn get_workspace(dir: &impl AsRef<Path>) -> Option<Vec<String>> {
-    if let Some(w) = cargo_toml(dir).workspace {
-        return Some(w.members);
+/// Load the names and source files of all the examples in a package
+///
+/// This follows the same rules as cargo: examples listed in `[[example]]` sections (with custom
+/// paths if set) and, unless `autoexamples = false`, examples discovered in the examples folder,
+/// including multi-file examples in `examples/<name>/main.rs`.
+pub fn load_examples(dir: &impl AsRef<Path>) -> Result<Vec<(String, PathBuf)>, Error> {
+    let file = join(dir, "Cargo.toml");
+    let manifest = Manifest::from_path(&file).map_err(|e| manifest_error(&file, e))?;
+    if manifest.package.is_none() {
+        return Ok(vec![]);
     }
-    None
+    manifest
+        .example
+        .iter()
+        .map(|e| {
+            let name = e.name.clone().ok_or_else(|| {
+                Error::new(format!("Example without a name in {}", file.display()))
+            })?;
+            let path = if let Some(p) = &e.path {
+                join(dir, p)
+            } else {
+                let file = join(&join(dir, "examples"), &format!("{name}.rs"));
+                if file.is_file() {
+                    file
+                } else {
+                    join(&join(&join(dir, "examples"), &name), "main.rs")
+                }
+            };
+            Ok((name, path))
+        })
+        .collect()
 }
 
 /// Load required features for an example
-pub fn load_required_features(dir: &impl AsRef<Path>, eg: &str) -> Vec<String> {
-    for e in cargo_toml(dir).example {
+pub fn load_required_features(dir: &impl AsRef<Path>, eg: &str) -> Result<Vec<String>, Error> {
+    for e in cargo_toml(dir)?.example {
         if Some(eg) == e.name.as_deref() {
-            return e.required_features;
+            return Ok(e.required_features);
         }
     }
-    vec![]
+    Ok(vec![])
+}
+
+/// Load the targets that an example should be run for
+pub fn load_targets(metadata: &Metadata, eg: &str) -> Result<Vec<String>, Error> {
+    if let Some(t) = metadata.example_option(eg, "target")? {
+        t.as_string_list()
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Load the build types that an example should be run with
+pub fn load_builds(metadata: &Metadata, eg: &str) -> Result<Option<Vec<BuildType>>, Error> {
+    if let Some(b) = metadata.example_option(eg, "build")? {
+        Ok(Some(b.as_build_types(&load_profiles(&metadata.dir)?)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Load the number of times that an example should be retried if it fails
+pub fn load_retries(metadata: &Metadata, eg: &str) -> Result<Option<usize>, Error> {
+    if let Some(r) = metadata.example_option(eg, "retries")? {
+        Ok(Some(r.as_usize()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Load the feature sets that an example should be run with
+pub fn load_feature_sets(metadata: &Metadata, eg: &str) -> Result<Option<Vec<Vec<String>>>, Error> {
+    if let Some(f) = metadata.example_option(eg, "feature-sets")? {
+        Ok(Some(f.as_feature_sets(&load_available_features(
+            &metadata.dir,
+        )?)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Check the templated-examples metadata of the examples in a package
+///
+/// Warnings are returned for metadata set for examples that do not exist and for unknown options.
+pub fn check_example_metadata(
+    metadata: &Metadata,
+    examples: &[String],
+) -> Result<Vec<Error>, Error> {
+    let mut warnings = vec![];
+    if let Some(section) = metadata_section(&metadata.dir, &["package", "example"])? {
+        for e in section.entries()? {
+            if let Some(t) = e.get("templated-examples") {
+                if !examples.iter().any(|eg| eg == e.key()) {
+                    warnings.push(Error::warning(format!(
+                        "templated-examples metadata set for example \"{}\", which does not exist",
+                        e.key()
+                    ))
+                    .at(t.location()));
+                } else {
+                    check_example_options(&t, e.key(), &mut warnings)?;
+                }
+            }
+        }
+    }
+    // The configuration file can be shared by the packages of a workspace, so its sections are
+    // not checked against the examples of each package
+    if let Some(c) = &metadata.config
+        && let Some(section) = c.get("example")
+    {
+        for e in section.entries()? {
+            check_example_options(&e, e.key(), &mut warnings)?;
+        }
+    }
+    Ok(warnings)
+}
+
+/// Check the options set for an example, adding warnings for unknown options
+fn check_example_options(
+    section: &Entry,
+    eg: &str,
+    warnings: &mut Vec<Error>,
+) -> Result<(), Error> {
+    for o in section.entries()? {
+        if !EXAMPLE_OPTIONS.contains(&o.key()) {
+            warnings.push(
+                Error::warning(format!(
+                    "Unknown option \"{}\" for example \"{eg}\"",
+                    o.key()
+                ))
+                .at(o.location()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Load the names of the profiles that can be used for a package
+///
+/// This includes the built-in profiles and the custom profiles defined in the Cargo.toml of the
+/// workspace, or of the package if it is not in a workspace: like cargo, profiles defined by
+/// members of a workspace are ignored. Custom profiles are only included if they inherit from a
+/// profile that can be used.
+pub fn load_profiles(dir: &impl AsRef<Path>) -> Result<Vec<String>, Error> {
+    let root = find_workspace_root(dir)?.unwrap_or_else(|| PathBuf::from(dir.as_ref()));
+    let custom = cargo_toml(&root)?.profile.custom;
+    let mut profiles = BUILT_IN_PROFILES.map(String::from).to_vec();
+    loop {
+        let count = profiles.len();
+        for (name, p) in &custom {
+            if !profiles.contains(name) && p.inherits.as_ref().is_some_and(|i| profiles.contains(i))
+            {
+                profiles.push(name.clone());
+            }
+        }
+        if profiles.len() == count {
+            return Ok(profiles);
+        }
+    }
+}
+
+/// Load the name of a package, or None if the Cargo.toml is a virtual manifest
+pub fn load_package_name(dir: &impl AsRef<Path>) -> Result<Option<String>, Error> {
+    Ok(cargo_toml(dir)?.package.map(|p| p.name))
+}
+
+/// Load the names of the dependencies of a package, including target-specific dependencies
+pub fn load_dependencies(dir: &impl AsRef<Path>) -> Result<Vec<String>, Error> {
+    let manifest = cargo_toml(dir)?;
+    let mut dependencies = manifest.dependencies.keys().cloned().collect::<Vec<_>>();
+    for t in manifest.target.values() {
+        for d in t.dependencies.keys() {
+            if !dependencies.contains(d) {
+                dependencies.push(d.clone());
+            }
+        }
+    }
+    Ok(dependencies)
+}
+
+/// Load the directories of the local path dependencies of a package
+///
+/// This includes normal, dev and build dependencies, and dependencies whose path is inherited
+/// from the workspace.
+pub fn load_path_dependencies(dir: &impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let dir = normalise(dir.as_ref());
+    let manifest = cargo_toml(&dir)?;
+    let root = find_workspace_root(&dir)?;
+    let workspace_dependencies = match &root {
+        Some(r) => cargo_toml(r)?
+            .workspace
+            .map(|w| w.dependencies)
+            .unwrap_or_default(),
+        None => Default::default(),
+    };
+    let mut sets = vec![
+        &manifest.dependencies,
+        &manifest.dev_dependencies,
+        &manifest.build_dependencies,
+    ];
+    for t in manifest.target.values() {
+        sets.extend([&t.dependencies, &t.dev_dependencies, &t.build_dependencies]);
+    }
+    let mut paths = vec![];
+    for (name, d) in sets.into_iter().flatten() {
+        let path = match d {
+            Dependency::Inherited(_) => workspace_dependencies
+                .get(name)
+                .and_then(|d| d.detail())
+                .and_then(|d| d.path.as_ref())
+                .zip(root.as_ref())
+                .map(|(p, r)| normalise(&r.join(p))),
+            _ => d
+                .detail()
+                .and_then(|d| d.path.as_ref())
+                .map(|p| normalise(&dir.join(p))),
+        };
+        if let Some(p) = path
+            && !paths.contains(&p)
+        {
+            paths.push(p);
+        }
+    }
+    Ok(paths)
 }
 
 /// Load available features for a crate
-pub fn load_available_features(dir: &impl AsRef<Path>) -> Vec<String> {
-    cargo_toml(dir)
+///
+/// This includes the features defined in [features] and the implicit features of optional
+/// dependencies. Optional dependencies that are referred to using `dep:` in [features] do not
+/// have an implicit feature.
+pub fn load_available_features(dir: &impl AsRef<Path>) -> Result<Vec<String>, Error> {
+    let manifest = cargo_toml(dir)?;
+    let mut features = manifest.features.keys().cloned().collect::<Vec<_>>();
+    let hidden = manifest
         .features
+        .values()
+        .flatten()
+        .filter_map(|f| f.strip_prefix("dep:"))
+        .collect::<Vec<_>>();
+    let optional = manifest
+        .dependencies
         .iter()
-        .map(|i| i.0.clone())
-        .collect::<Vec<_>>()
+        .chain(manifest.target.values().flat_map(|t| t.dependencies.iter()))
+        .filter(|(_, d)| d.optional())
+        .map(|(name, _)| name);
+    for d in optional {
+        if !hidden.contains(&d.as_str()) && !features.contains(d) {
+            features.push(d.clone());
+        }
+    }
+    Ok(features)
 }
 
-/// Load command from Cargo.toml section [package.metedata.example.{{eg}}.templated-examples]
-pub fn load_command(dir: &impl AsRef<Path>, eg: &str) -> Option<CargoCommand> {
-    if let Some(p) = cargo_toml(dir).package
-        && let Some(m) = p.metadata
-        && let Some(e) = m.get("example")
-        && let Some(ex) = e.get(eg)
-        && let Some(d) = ex.get("templated-examples")
+/// Load the command set for an example in its metadata or the configuration file
+///
+/// A command is only set if the sections for the example set the command, build type or features.
+/// Other options, such as retries and targets, are applied to the command wherever it is set.
+pub fn load_command(metadata: &Metadata, eg: &str) -> Result<Option<CargoCommand>, Error> {
+    let sections = metadata.example_sections(eg)?;
+    let get = |key| sections.iter().find_map(|d| d.get(key));
+    if ["command", "build", "no-default-features", "all-features"]
+        .into_iter()
+        .all(|k| get(k).is_none())
     {
-        let mut cmd = if let Some(c) = d.get("command") {
-            CargoCommand::from_str(
-                c.as_str()
-                    .expect("Command must be a string for example \"{eg}\""),
-                eg,
-            )
+        return Ok(None);
+    }
+    let mut cmd = if let Some(c) = get("command") {
+        let command = c.as_str()?;
+        // Point to individual words in the command if the string contains no escaped characters
+        if let Some(l) = c.location()
+            && l.length == command.chars().count() + 2
+        {
+            CargoCommand::from_str(command, eg, Some(l.part(1, command.chars().count())))?
         } else {
-            CargoCommand::new(String::from(eg))
-        };
-        if let Some(b) = d.get("build") {
-            cmd.set_build_type(&BuildType::from_str(
-                b.as_str()
-                    .expect("Command must be a string for example \"{eg}\""),
-            ));
+            let mut cmd =
+                CargoCommand::from_str(command, eg, None).map_err(|e| e.or_at(c.location()))?;
+            cmd.set_location(c.location());
+            cmd
         }
-        Some(cmd)
     } else {
-        None
+        let mut cmd = CargoCommand::new(String::from(eg));
+        cmd.set_location(sections[0].location());
+        cmd
+    };
+    if let Some(b) = get("build") {
+        cmd.set_build_types(&b.as_build_types(&load_profiles(&metadata.dir)?)?)
+            .map_err(|e| e.at(b.location()))?;
+    }
+    if let Some(f) = get("no-default-features")
+        && f.as_bool()?
+    {
+        cmd.set_no_default_features();
+    }
+    if let Some(f) = get("all-features")
+        && f.as_bool()?
+    {
+        cmd.set_all_features();
+    }
+    Ok(Some(cmd))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn package(dir: &TempDir, path: &str, name: &str) {
+        dir.write(
+            &format!("{path}/Cargo.toml"),
+            &format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+        );
+    }
+
+    #[test]
+    fn test_get_workspace() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"tools/cli\", \"./crates/a\"]\nexclude = [\"crates/skip\"]\n",
+        );
+        package(&dir, "crates/b", "b");
+        package(&dir, "crates/a", "a");
+        package(&dir, "crates/skip", "skip");
+        package(&dir, "tools/cli", "cli");
+        // Directories matched by a glob pattern that are not crates are ignored
+        fs::create_dir_all(dir.join("crates/docs")).unwrap();
+
+        assert_eq!(
+            get_workspace(&dir.path()).unwrap(),
+            Some(vec![
+                dir.join("crates/a"),
+                dir.join("crates/b"),
+                dir.join("tools/cli"),
+            ])
+        );
+        assert_eq!(
+            find_workspace_root(&dir.join("crates/a")).unwrap(),
+            Some(dir.path().to_path_buf())
+        );
+        assert_eq!(get_workspace(&dir.join("crates/a")).unwrap(), None);
+
+        // A member that is not a glob pattern must exist
+        dir.write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"tools/missing\"]\n",
+        );
+        let e = get_workspace(&dir.path()).unwrap_err().to_string();
+        assert!(e.contains("Could not find Cargo.toml for workspace member \"tools/missing\""));
+        assert!(e.contains("Cargo.toml:2:"));
+    }
+
+    #[test]
+    fn test_load_examples() {
+        let dir = TempDir::new();
+        let manifest = |autoexamples: bool| {
+            format!(
+                "[package]\nname = \"examples\"\nversion = \"0.1.0\"\nautoexamples = {autoexamples}\n\n[[example]]\nname = \"custom\"\npath = \"demos/custom.rs\"\n"
+            )
+        };
+        dir.write("src/lib.rs", "");
+        dir.write("examples/single.rs", "fn main() {}\n");
+        dir.write("examples/multi/main.rs", "mod other;\nfn main() {}\n");
+        dir.write("examples/multi/other.rs", "");
+        dir.write("demos/custom.rs", "fn main() {}\n");
+        let load = || {
+            let mut examples = load_examples(&dir.path()).unwrap();
+            examples.sort();
+            examples
+        };
+
+        dir.write("Cargo.toml", &manifest(true));
+        assert_eq!(
+            load(),
+            [
+                (String::from("custom"), dir.join("demos/custom.rs")),
+                (String::from("multi"), dir.join("examples/multi/main.rs")),
+                (String::from("single"), dir.join("examples/single.rs")),
+            ]
+        );
+
+        // Only the examples listed in Cargo.toml are used if autoexamples is false
+        dir.write("Cargo.toml", &manifest(false));
+        assert_eq!(
+            load(),
+            [(String::from("custom"), dir.join("demos/custom.rs"))]
+        );
+    }
+
+    #[test]
+    fn test_workspace_metadata() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"
+[workspace]
+members = ["a", "b"]
+
+[workspace.metadata.templated-examples]
+NPROCESSES = ["1", "2"]
+SIZE = ["small"]
+build = "release"
+retries = 2
+"#,
+        );
+        package(&dir, "a", "a");
+        dir.write(
+            "b/Cargo.toml",
+            r#"
+[package]
+name = "b"
+version = "0.1.0"
+
+[package.metadata.templated-examples]
+SIZE = ["large"]
+MODE = ["fast"]
+build = "debug"
+"#,
+        );
+
+        // A member without its own metadata inherits the workspace's values
+        let metadata = Metadata::load(&dir.join("a"), None, None).unwrap();
+        let mut args = BTreeMap::new();
+        load_args(&metadata, &mut args).unwrap();
+        assert_eq!(args["NPROCESSES"], ["1", "2"]);
+        assert_eq!(args["SIZE"], ["small"]);
+        assert_eq!(get_default_builds(&metadata).unwrap()[0].name(), "release");
+        assert_eq!(get_default_number(&metadata, "retries").unwrap(), Some(2));
+
+        // A member's values override and extend the workspace's values
+        let metadata = Metadata::load(&dir.join("b"), None, None).unwrap();
+        let mut args = BTreeMap::new();
+        load_args(&metadata, &mut args).unwrap();
+        assert_eq!(args["NPROCESSES"], ["1", "2"]);
+        assert_eq!(args["SIZE"], ["large"]);
+        assert_eq!(args["MODE"], ["fast"]);
+        assert_eq!(get_default_builds(&metadata).unwrap()[0].name(), "debug");
+        assert_eq!(get_default_number(&metadata, "retries").unwrap(), Some(2));
     }
 }
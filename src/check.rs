@@ -0,0 +1,201 @@
+//! Check the configuration of examples without running them
+
+use crate::{
+    cargo_toml,
+    command_line::SpecialArgs,
+    error::{Error, Location},
+    plan::{self, Package},
+};
+use std::{collections::BTreeMap, path::Path};
+
+/// A template argument that was given values, and whether any example used it
+struct Definition {
+    name: String,
+    location: Option<Location>,
+    used: bool,
+}
+
+/// Check the examples of a package, adding any problems to `errors`
+fn check_package(p: &Package, definitions: &mut Vec<Definition>, errors: &mut Vec<Error>) {
+    let mut check = || -> Result<(), Error> {
+        let names = cargo_toml::load_examples(&p.dir)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        errors.extend(cargo_toml::check_example_metadata(&p.metadata, &names)?);
+
+        for e in &p.examples {
+            let location = e.command.location().cloned();
+            for a in e.command.template_args() {
+                if !p.template_args.contains_key(&a) {
+                    errors.push(
+                        Error::new(format!(
+                            "Template argument \"{a}\" is used in the command for example \"{}\" but is never given a value",
+                            e.name
+                        ))
+                        .at(location.clone()),
+                    );
+                }
+            }
+            // Features of dependencies (eg "serde/derive") are checked by cargo
+            for f in e.command.features() {
+                if !f.contains("{{") && !f.contains('/') && !p.available_features.contains(f) {
+                    errors.push(
+                        Error::new(format!(
+                            "Feature \"{f}\" used by example \"{}\" is not defined in [features]",
+                            e.name
+                        ))
+                        .at(location.clone()),
+                    );
+                }
+            }
+        }
+
+        let locations = cargo_toml::load_arg_locations(&p.metadata);
+        for name in p.template_args.keys() {
+            let location = locations.get(name).cloned().flatten();
+            let used = p
+                .examples
+                .iter()
+                .any(|e| e.command.template_args().contains(name));
+            if let Some(d) = definitions
+                .iter_mut()
+                .find(|d| d.name == *name && d.location == location)
+            {
+                d.used |= used;
+            } else {
+                definitions.push(Definition {
+                    name: name.clone(),
+                    location,
+                    used,
+                });
+            }
+        }
+        Ok(())
+    };
+    if let Err(e) = check() {
+        errors.push(e);
+    }
+}
+
+/// Check the configuration of all examples in a directory, returning all problems found
+pub fn check(
+    dir: &Path,
+    special_args: &SpecialArgs,
+    cli_args: &BTreeMap<String, Vec<String>>,
+) -> Vec<Error> {
+    let mut packages = vec![];
+    let mut errors = vec![];
+    plan::load_packages(
+        dir,
+        special_args,
+        cli_args,
+        false,
+        &mut vec![],
+        &mut packages,
+        &mut errors,
+    );
+    errors.extend(plan::check_preset(
+        &packages,
+        special_args.preset.as_deref(),
+    ));
+    errors.extend(plan::check_command_line_features(
+        &packages,
+        &special_args.features,
+    ));
+
+    let mut definitions = vec![];
+    for p in &packages {
+        check_package(p, &mut definitions, &mut errors);
+    }
+    for d in definitions {
+        if !d.used {
+            errors.push(
+                Error::warning(format!(
+                    "Template argument \"{}\" is given values but is not used by any example",
+                    d.name
+                ))
+                .at(d.location),
+            );
+        }
+    }
+
+    // Examples that are run for multiple targets can lead to the same problem being found repeatedly
+    let mut unique = vec![];
+    for e in errors {
+        if !unique.contains(&e) {
+            unique.push(e);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{command_line, temp_dir::TempDir};
+
+    #[test]
+    fn test_check() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"
+[package]
+name = "checked"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+one = []
+
+[package.metadata.templated-examples]
+bulid = "debug"
+retires = 2
+N = ["1", "2"]
+UNUSED = ["1"]
+
+[package.metadata.example.demo.templated-examples]
+retires = 2
+
+[package.metadata.example.missing.templated-examples]
+retries = 1
+
+[package.metadata.example.mismatched.templated-examples]
+command = "run --release"
+"#,
+        );
+        dir.write(
+            "examples/demo.rs",
+            "//? mpirun -n {{N}} -x {{M}} --features \"one,two\"\nfn main() {}\n",
+        );
+        dir.write(
+            "examples/profile.rs",
+            "//? run --profile nope\nfn main() {}\n",
+        );
+        dir.write("examples/mismatched.rs", "//? run\nfn main() {}\n");
+        let (special_args, cli_args) = command_line::parse(&[]).unwrap();
+        let errors = check(dir.path(), &special_args, &cli_args)
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        let expected = [
+            "error: Unknown option or invalid template argument \"bulid\"",
+            "error: Unknown option or invalid template argument \"retires\"",
+            "warning: Unknown option \"retires\" for example \"demo\"",
+            "warning: templated-examples metadata set for example \"missing\", which does not exist",
+            "error: Template argument \"M\" is used in the command for example \"demo\" but is never given a value",
+            "warning: Template argument \"UNUSED\" is given values but is not used by any example",
+            "error: Feature \"two\" used by example \"demo\" is not defined in [features]",
+            "error: Profile \"nope\" used by example \"profile\" is not defined",
+            "error: Commands set in file and Cargo.toml do not match for example \"mismatched\"",
+        ];
+        for message in expected {
+            assert!(
+                errors.iter().any(|e| e.starts_with(message)),
+                "{message} not found in {errors:#?}"
+            );
+        }
+        assert_eq!(errors.len(), expected.len());
+    }
+}
@@ -1,49 +1,472 @@
 //! Functions to read command line input
 
-use std::{collections::HashMap, env};
+use crate::{
+    BuildType,
+    baseline::{check_name, parse_threshold},
+    error::Error,
+};
+use std::{collections::BTreeMap, env};
+
+/// What cargo-templated-examples should do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Run the examples
+    Run,
+    /// Check the configuration of the examples without running them
+    Check,
+    /// Run each example repeatedly and report statistics of the times taken
+    Bench,
+}
+
+/// How combinations of features are generated for each example
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureCombinations {
+    /// Run with each feature on its own, set by --each-feature
+    EachFeature,
+    /// Run with every combination of features, set by --feature-powerset
+    Powerset,
+}
 
 /// Special command line arguments
 pub struct SpecialArgs {
+    /// The subcommand
+    pub mode: Mode,
     /// --features
     pub features: Vec<String>,
+    /// --no-default-features
+    pub no_default_features: bool,
+    /// --all-features
+    pub all_features: bool,
+    /// --each-feature or --feature-powerset
+    pub feature_combinations: Option<FeatureCombinations>,
+    /// --depth
+    pub depth: Option<usize>,
+    /// --exclude-features
+    pub exclude_features: Vec<String>,
+    /// The build type set by --release, --debug or --profile
+    pub build: Option<BuildType>,
+    /// --force-build
+    pub force_build: bool,
+    /// --target
+    pub targets: Vec<String>,
+    /// --build-only-targets
+    pub build_only_targets: Vec<String>,
+    /// --incremental
+    pub incremental: bool,
+    /// --rerun-failed
+    pub rerun_failed: bool,
+    /// The number of failures after which no more runs are started, set by --fail-fast,
+    /// --no-fail-fast or --max-failures
+    pub max_failures: Option<usize>,
+    /// --retries
+    pub retries: Option<usize>,
+    /// --slowest
+    pub slowest: Option<usize>,
+    /// --warmup
+    pub warmup: usize,
+    /// --repeats
+    pub repeats: usize,
+    /// --export-csv
+    pub export_csv: Option<String>,
+    /// --export-json
+    pub export_json: Option<String>,
+    /// --save-baseline
+    pub save_baseline: Option<String>,
+    /// --baseline
+    pub baseline: Option<String>,
+    /// --threshold, as a fraction
+    pub threshold: f64,
+    /// --fail-on-regression
+    pub fail_on_regression: bool,
+    /// --config
+    pub config: Option<String>,
+    /// --preset
+    pub preset: Option<String>,
 }
 
 impl SpecialArgs {
     fn new() -> Self {
-        Self { features: vec![] }
+        Self {
+            mode: Mode::Run,
+            features: vec![],
+            no_default_features: false,
+            all_features: false,
+            feature_combinations: None,
+            depth: None,
+            exclude_features: vec![],
+            build: None,
+            force_build: false,
+            targets: vec![],
+            build_only_targets: vec![],
+            incremental: false,
+            rerun_failed: false,
+            max_failures: None,
+            retries: None,
+            slowest: None,
+            warmup: 1,
+            repeats: 5,
+            export_csv: None,
+            export_json: None,
+            save_baseline: None,
+            baseline: None,
+            threshold: 0.1,
+            fail_on_regression: false,
+            config: None,
+            preset: None,
+        }
     }
 }
 
-/// Load template arguments input via the command line
-pub fn load_args(args: &mut HashMap<String, Vec<String>>) {
+/// Load the command line input, checking that it was run as a cargo subcommand
+fn input_args() -> Result<Vec<String>, Error> {
     let input_args = env::args().collect::<Vec<_>>();
-    assert_eq!(input_args[1], "templated-examples");
-    for i in 1..input_args.len() / 2 {
-        if input_args[2 * i] == "--features" {
-            continue;
-        }
-        args.insert(
-            input_args[2 * i].clone(),
-            input_args[2 * i + 1]
-                .split(",")
-                .map(String::from)
-                .collect::<Vec<_>>(),
-        );
+    if input_args.get(1).map(String::as_str) != Some("templated-examples") {
+        return Err(Error::new(
+            "cargo-templated-examples must be run using `cargo templated-examples`",
+        ));
     }
+    Ok(input_args[2..].to_vec())
 }
 
-/// Load special arguments input via the command line
-pub fn load_special_args() -> SpecialArgs {
-    let mut args = SpecialArgs::new();
-    let input_args = env::args().collect::<Vec<_>>();
-    assert_eq!(input_args[1], "templated-examples");
-    for i in 1..input_args.len() / 2 {
-        if input_args[2 * i] == "--features" {
-            args.features = input_args[2 * i + 1]
-                .split(",")
-                .map(String::from)
-                .collect::<Vec<_>>();
+/// Read the comma-separated list of values that follows an argument
+fn next_list<'a>(
+    input_args: &mut impl Iterator<Item = &'a String>,
+    arg: &str,
+) -> Result<Vec<String>, Error> {
+    Ok(input_args
+        .next()
+        .ok_or_else(|| {
+            Error::new(format!(
+                "{arg} must be followed by a comma-separated list of values"
+            ))
+        })?
+        .split(",")
+        .map(String::from)
+        .collect::<Vec<_>>())
+}
+
+/// Read the non-negative integer that follows an argument
+fn next_number<'a>(
+    input_args: &mut impl Iterator<Item = &'a String>,
+    arg: &str,
+) -> Result<usize, Error> {
+    input_args
+        .next()
+        .and_then(|n| n.parse::<usize>().ok())
+        .ok_or_else(|| Error::new(format!("{arg} must be followed by a non-negative integer")))
+}
+
+/// Read the value that follows an argument
+fn next_value<'a>(
+    input_args: &mut impl Iterator<Item = &'a String>,
+    arg: &str,
+) -> Result<String, Error> {
+    input_args
+        .next()
+        .cloned()
+        .ok_or_else(|| Error::new(format!("{arg} must be followed by a value")))
+}
+
+/// Split command line input into special arguments and template arguments
+pub fn parse(input_args: &[String]) -> Result<(SpecialArgs, BTreeMap<String, Vec<String>>), Error> {
+    let mut special_args = SpecialArgs::new();
+    let mut args = BTreeMap::new();
+    let mut input_args = input_args.iter().peekable();
+    match input_args.peek().map(|a| a.as_str()) {
+        Some("check") => {
+            special_args.mode = Mode::Check;
+            input_args.next();
+        }
+        Some("bench") => {
+            special_args.mode = Mode::Bench;
+            input_args.next();
+        }
+        _ => {}
+    }
+    while let Some(a) = input_args.next() {
+        match a.as_str() {
+            "--features" => {
+                special_args.features = next_list(&mut input_args, a)?;
+            }
+            "--each-feature" | "--feature-powerset" => {
+                if special_args.feature_combinations.is_some() {
+                    return Err(Error::new(
+                        "--each-feature and --feature-powerset cannot be used together",
+                    ));
+                }
+                special_args.feature_combinations = Some(if a == "--each-feature" {
+                    FeatureCombinations::EachFeature
+                } else {
+                    FeatureCombinations::Powerset
+                });
+            }
+            "--depth" => {
+                let n = next_number(&mut input_args, a)?;
+                if n == 0 {
+                    return Err(Error::new("--depth must be at least 1"));
+                }
+                special_args.depth = Some(n);
+            }
+            "--exclude-features" => {
+                special_args.exclude_features = next_list(&mut input_args, a)?;
+            }
+            "--release" | "--debug" | "--profile" => {
+                if special_args.build.is_some() {
+                    return Err(Error::new(
+                        "Only one of --release, --debug and --profile can be used",
+                    ));
+                }
+                special_args.build = Some(match a.as_str() {
+                    "--release" => BuildType::Release,
+                    "--debug" => BuildType::Debug,
+                    _ => BuildType::from_str(&next_value(&mut input_args, a)?),
+                });
+            }
+            "--force-build" => {
+                special_args.force_build = true;
+            }
+            "--target" => {
+                special_args.targets = next_list(&mut input_args, a)?;
+            }
+            "--build-only-targets" => {
+                special_args.build_only_targets = next_list(&mut input_args, a)?;
+            }
+            "--no-default-features" => {
+                special_args.no_default_features = true;
+            }
+            "--all-features" => {
+                special_args.all_features = true;
+            }
+            "--incremental" => {
+                special_args.incremental = true;
+            }
+            "--rerun-failed" => {
+                special_args.rerun_failed = true;
+            }
+            "--fail-fast" => {
+                special_args.max_failures = Some(1);
+            }
+            "--no-fail-fast" => {
+                special_args.max_failures = None;
+            }
+            "--retries" => {
+                special_args.retries = Some(next_number(&mut input_args, a)?);
+            }
+            "--slowest" => {
+                special_args.slowest = Some(next_number(&mut input_args, a)?);
+            }
+            "--max-failures" => {
+                let n = next_number(&mut input_args, a)?;
+                if n == 0 {
+                    return Err(Error::new("--max-failures must be at least 1"));
+                }
+                special_args.max_failures = Some(n);
+            }
+            "--warmup" => {
+                special_args.warmup = next_number(&mut input_args, a)?;
+            }
+            "--repeats" => {
+                let n = next_number(&mut input_args, a)?;
+                if n == 0 {
+                    return Err(Error::new("--repeats must be at least 1"));
+                }
+                special_args.repeats = n;
+            }
+            "--export-csv" => {
+                special_args.export_csv = Some(next_value(&mut input_args, a)?);
+            }
+            "--export-json" => {
+                special_args.export_json = Some(next_value(&mut input_args, a)?);
+            }
+            "--save-baseline" => {
+                let name = next_value(&mut input_args, a)?;
+                check_name(&name)?;
+                special_args.save_baseline = Some(name);
+            }
+            "--baseline" => {
+                let name = next_value(&mut input_args, a)?;
+                check_name(&name)?;
+                special_args.baseline = Some(name);
+            }
+            "--threshold" => {
+                special_args.threshold = parse_threshold(&next_value(&mut input_args, a)?)?;
+            }
+            "--fail-on-regression" => {
+                special_args.fail_on_regression = true;
+            }
+            "--config" => {
+                special_args.config = Some(next_value(&mut input_args, a)?);
+            }
+            "--preset" => {
+                special_args.preset = Some(next_value(&mut input_args, a)?);
+            }
+            _ => {
+                args.insert(a.clone(), next_list(&mut input_args, a)?);
+            }
         }
     }
-    args
+    if special_args.force_build && special_args.build.is_none() {
+        return Err(Error::new(
+            "--force-build must be used with --release, --debug or --profile",
+        ));
+    }
+    if special_args.depth.is_some()
+        && special_args.feature_combinations != Some(FeatureCombinations::Powerset)
+    {
+        return Err(Error::new(
+            "--depth can only be used with --feature-powerset",
+        ));
+    }
+    Ok((special_args, args))
+}
+
+/// Load the special arguments and the values of template arguments input via the command line
+pub fn load() -> Result<(SpecialArgs, BTreeMap<String, Vec<String>>), Error> {
+    parse(&input_args()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_args(input: &[&str]) -> Vec<String> {
+        input.iter().map(|a| String::from(*a)).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_parse() {
+        let (special_args, args) = parse(&to_args(&[
+            "NPROCESSES",
+            "1,5",
+            "--no-default-features",
+            "--features",
+            "one,two",
+            "FEATURE",
+            "two",
+        ]))
+        .unwrap();
+        assert_eq!(special_args.mode, Mode::Run);
+        assert_eq!(special_args.features, to_args(&["one", "two"]));
+        assert!(special_args.no_default_features);
+        assert!(!special_args.all_features);
+        assert_eq!(args.len(), 2);
+        assert_eq!(args["NPROCESSES"], to_args(&["1", "5"]));
+        assert_eq!(args["FEATURE"], to_args(&["two"]));
+        assert!(parse(&to_args(&["NPROCESSES"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        let (special_args, _) = parse(&to_args(&[
+            "--target",
+            "x86_64-unknown-linux-musl,wasm32-wasip1",
+            "--build-only-targets",
+            "wasm32-wasip1",
+        ]))
+        .unwrap();
+        assert_eq!(
+            special_args.targets,
+            to_args(&["x86_64-unknown-linux-musl", "wasm32-wasip1"])
+        );
+        assert_eq!(special_args.build_only_targets, to_args(&["wasm32-wasip1"]));
+    }
+
+    #[test]
+    fn test_parse_incremental() {
+        let (special_args, _) = parse(&to_args(&["--incremental"])).unwrap();
+        assert!(special_args.incremental);
+        assert!(!special_args.rerun_failed);
+    }
+
+    #[test]
+    fn test_parse_failures() {
+        let (special_args, _) = parse(&[]).unwrap();
+        assert_eq!(special_args.max_failures, None);
+        assert_eq!(special_args.retries, None);
+        let (special_args, _) = parse(&to_args(&["--fail-fast"])).unwrap();
+        assert_eq!(special_args.max_failures, Some(1));
+        let (special_args, _) =
+            parse(&to_args(&["--max-failures", "3", "--retries", "2"])).unwrap();
+        assert_eq!(special_args.max_failures, Some(3));
+        assert_eq!(special_args.retries, Some(2));
+        let (special_args, _) = parse(&to_args(&["--fail-fast", "--no-fail-fast"])).unwrap();
+        assert_eq!(special_args.max_failures, None);
+        assert!(parse(&to_args(&["--max-failures", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_bench() {
+        let (special_args, _) = parse(&to_args(&[
+            "bench",
+            "--repeats",
+            "10",
+            "--export-csv",
+            "times.csv",
+        ]))
+        .unwrap();
+        assert_eq!(special_args.mode, Mode::Bench);
+        assert_eq!(special_args.warmup, 1);
+        assert_eq!(special_args.repeats, 10);
+        assert_eq!(special_args.export_csv.as_deref(), Some("times.csv"));
+        assert!(parse(&to_args(&["bench", "--repeats", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_baseline() {
+        let (special_args, _) =
+            parse(&to_args(&["--baseline", "main", "--threshold", "5%"])).unwrap();
+        assert_eq!(special_args.baseline.as_deref(), Some("main"));
+        assert_eq!(special_args.threshold, 0.05);
+        assert!(!special_args.fail_on_regression);
+    }
+
+    #[test]
+    fn test_parse_feature_combinations() {
+        let (special_args, _) = parse(&to_args(&[
+            "--feature-powerset",
+            "--depth",
+            "2",
+            "--exclude-features",
+            "one",
+        ]))
+        .unwrap();
+        assert_eq!(
+            special_args.feature_combinations,
+            Some(FeatureCombinations::Powerset)
+        );
+        assert_eq!(special_args.depth, Some(2));
+        assert_eq!(special_args.exclude_features, to_args(&["one"]));
+        assert!(parse(&to_args(&["--each-feature", "--depth", "2"])).is_err());
+        assert!(parse(&to_args(&["--each-feature", "--feature-powerset"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_build_type() {
+        let (special_args, _) = parse(&to_args(&["--profile", "fast", "--force-build"])).unwrap();
+        assert_eq!(
+            special_args.build,
+            Some(BuildType::Profile(String::from("fast")))
+        );
+        assert!(special_args.force_build);
+        assert!(parse(&to_args(&["--release", "--debug"])).is_err());
+        assert!(parse(&to_args(&["--force-build"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_and_preset() {
+        let (special_args, _) = parse(&to_args(&["--config", "ci.toml"])).unwrap();
+        assert_eq!(special_args.config.as_deref(), Some("ci.toml"));
+        assert!(parse(&to_args(&["--config"])).is_err());
+
+        let (special_args, args) = parse(&to_args(&["--preset", "ci", "N", "2"])).unwrap();
+        assert_eq!(special_args.preset.as_deref(), Some("ci"));
+        assert_eq!(args["N"], to_args(&["2"]));
+    }
+
+    #[test]
+    fn test_parse_check() {
+        let (special_args, args) = parse(&to_args(&["check", "NPROCESSES", "2"])).unwrap();
+        assert_eq!(special_args.mode, Mode::Check);
+        assert_eq!(args["NPROCESSES"], to_args(&["2"]));
+    }
 }
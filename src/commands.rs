@@ -1,6 +1,10 @@
 //! Cargo commands
 
-use crate::parsing::parse_string_if_quoted;
+use crate::{
+    error::{Error, Location},
+    parsing::{parse_string_if_quoted, split_words},
+};
+use std::path::Path;
 
 /// A build type
 #[derive(Clone, Debug, PartialEq)]
@@ -24,18 +28,62 @@ impl BuildType {
             p => BuildType::Profile(p.to_string()),
         }
     }
+
+    /// Check whether the build type uses a profile that is in a list of defined profiles
+    ///
+    /// Profiles that contain template arguments are not checked.
+    pub fn is_defined(&self, profiles: &[String]) -> bool {
+        match self {
+            BuildType::Profile(p) => p.contains("{{") || profiles.contains(p),
+            _ => true,
+        }
+    }
+
+    /// Get the name of the build type
+    pub fn name(&self) -> &str {
+        match self {
+            BuildType::Debug => "debug",
+            BuildType::Release => "release",
+            BuildType::Profile(p) => p,
+            BuildType::Default => "default",
+        }
+    }
 }
 
 /// A command to be run
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct CargoCommand {
     run: String,
     example_name: String,
     args: Vec<(String, String)>,
     features: Vec<String>,
+    no_default_features: bool,
+    all_features: bool,
     available_features: Vec<String>,
     build: BuildType,
     package: Option<String>,
+    manifest_path: Option<String>,
+    target: Option<String>,
+    build_only: bool,
+    location: Option<Location>,
+}
+
+impl PartialEq for CargoCommand {
+    /// Compare two commands, ignoring where they were set
+    fn eq(&self, other: &Self) -> bool {
+        self.run == other.run
+            && self.example_name == other.example_name
+            && self.args == other.args
+            && self.features == other.features
+            && self.no_default_features == other.no_default_features
+            && self.all_features == other.all_features
+            && self.available_features == other.available_features
+            && self.build == other.build
+            && self.package == other.package
+            && self.manifest_path == other.manifest_path
+            && self.target == other.target
+            && self.build_only == other.build_only
+    }
 }
 
 impl CargoCommand {
@@ -46,22 +94,67 @@ impl CargoCommand {
             example_name,
             args: vec![],
             features: vec![],
+            no_default_features: false,
+            all_features: false,
             available_features: vec![],
             build: BuildType::Default,
             package: None,
+            manifest_path: None,
+            target: None,
+            build_only: false,
+            location: None,
         }
     }
 
+    /// Get the location where the command was set
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// Set the location where the command was set
+    pub fn set_location(&mut self, location: Option<Location>) {
+        self.location = location;
+    }
+
     /// Convert command to string
+    ///
+    /// If the command is build only, the example is built using `cargo build` and the
+    /// arguments of the run command are omitted.
     pub fn as_string(&self) -> String {
+        if self.build_only {
+            return self.build_string(&[&self.example_name]);
+        }
         let mut c = format!("cargo {}", self.run);
         for (key, value) in &self.args {
             c.push_str(&format!(" {key} {value}"));
         }
         c.push_str(&format!(" --example {}", self.example_name));
+        c.push_str(&self.flags());
+        c
+    }
+
+    /// Convert to a `cargo build` command that builds the given examples with the same options
+    pub fn build_string(&self, examples: &[&str]) -> String {
+        let mut c = String::from("cargo build");
+        for e in examples {
+            c.push_str(&format!(" --example {e}"));
+        }
+        c.push_str(&self.flags());
+        c
+    }
+
+    /// The options that affect how the example is built
+    fn flags(&self) -> String {
+        let mut c = String::new();
         if !self.features.is_empty() {
             c.push_str(&format!(" --features \"{}\"", self.features.join(",")));
         }
+        if self.no_default_features {
+            c.push_str(" --no-default-features");
+        }
+        if self.all_features {
+            c.push_str(" --all-features");
+        }
         match &self.build {
             BuildType::Debug => {}
             BuildType::Release => {
@@ -77,6 +170,16 @@ impl CargoCommand {
         if let Some(p) = &self.package {
             c.push_str(&format!(" --package {p}"));
         }
+        if let Some(m) = &self.manifest_path {
+            if m.contains(' ') {
+                c.push_str(&format!(" --manifest-path \"{m}\""));
+            } else {
+                c.push_str(&format!(" --manifest-path {m}"));
+            }
+        }
+        if let Some(t) = &self.target {
+            c.push_str(&format!(" --target {t}"));
+        }
         c
     }
 
@@ -90,18 +193,39 @@ impl CargoCommand {
         }
     }
 
-    /// Set build type, or panic if it has already been set to another non-default value
-    pub fn set_build_type(&mut self, build: &BuildType) {
-        self.set_default_build_type(build);
-        if self.build != *build {
-            panic!(
+    /// Set the build types that the example can be run with
+    ///
+    /// If only one build type is given, it is used as the build type of the command. If the build
+    /// type has already been set to a non-default value that is not one of the given build types,
+    /// an error is returned.
+    pub fn set_build_types(&mut self, builds: &[BuildType]) -> Result<(), Error> {
+        if let [build] = builds {
+            self.set_default_build_type(build);
+        }
+        if self.build != BuildType::Default && !builds.contains(&self.build) {
+            Err(Error::new(format!(
                 "Inconsistent build types set for example \"{}\"",
                 self.example_name
-            );
+            ))
+            .at(self.location.clone()))
+        } else {
+            Ok(())
         }
     }
 
+    /// Set build type, replacing any build type that has already been set
+    pub fn force_build_type(&mut self, build: &BuildType) {
+        if *build == BuildType::Default {
+            panic!("Cannot force build type to BuildType::Default");
+        }
+        self.build = build.clone();
+    }
+
+    /// Add features to the command if they are available in the package
     pub fn add_features_if_available(&mut self, features: &[String]) {
+        if self.all_features {
+            return;
+        }
         for f in features {
             if !self.features.contains(f) && self.available_features.contains(f) {
                 self.features.push(f.to_string());
@@ -109,145 +233,495 @@ impl CargoCommand {
         }
     }
 
+    /// Add features to the command, eg the features of a feature set
+    ///
+    /// If --all-features is used, the features are already enabled.
+    pub fn add_features(&mut self, features: &[String]) {
+        if self.all_features {
+            return;
+        }
+        for f in features {
+            if !self.features.contains(f) {
+                self.features.push(f.to_string());
+            }
+        }
+    }
+
     /// Set the available features
     pub fn set_available_features(&mut self, features: &[String]) {
         self.available_features = features.to_vec();
     }
 
+    /// Disable default features
+    pub fn set_no_default_features(&mut self) {
+        self.no_default_features = true;
+    }
+
+    /// Enable all features
+    pub fn set_all_features(&mut self) {
+        self.all_features = true;
+    }
+
     /// Set required features
-    pub fn set_required_features(&mut self, features: &[String]) {
+    ///
+    /// Required features are always passed explicitly, as they may be default features that are
+    /// disabled by --no-default-features. If --all-features is used, they are already enabled.
+    pub fn set_required_features(&mut self, features: &[String]) -> Result<(), Error> {
+        if self.all_features {
+            return Ok(());
+        }
         if self.features.is_empty() {
             self.features = features.to_vec();
         } else {
             for f in features {
                 if !self.features.contains(f) {
-                    panic!(
+                    return Err(Error::new(format!(
                         "Required feature \"{f}\" is missing from list of features in command for example \"{}\"",
                         self.example_name
-                    );
+                    ))
+                    .at(self.location.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the path to the Cargo.toml of the package that the example belongs to
+    pub fn set_manifest_path(&mut self, manifest_path: &Path) {
+        self.manifest_path = Some(manifest_path.display().to_string());
+    }
+
+    /// Get the target triple
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Set the target triple
+    pub fn set_target(&mut self, target: &str) {
+        self.target = Some(String::from(target));
+    }
+
+    /// Only build the example rather than running it
+    pub fn set_build_only(&mut self) {
+        self.build_only = true;
+    }
+
+    /// Is the example only built rather than run?
+    pub fn is_build_only(&self) -> bool {
+        self.build_only
+    }
+
+    /// Can the example be run by running its binary directly rather than using cargo?
+    ///
+    /// This is only possible for `cargo run` commands with no additional arguments that are built
+    /// for the host, as examples built for other targets may need a runner.
+    pub fn runs_binary(&self) -> bool {
+        !self.build_only && self.run == "run" && self.args.is_empty() && self.target.is_none()
+    }
+
+    /// Get the name of the example
+    pub fn example_name(&self) -> &str {
+        &self.example_name
+    }
+
+    /// Get the features
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    /// Get the build type
+    pub fn build(&self) -> &BuildType {
+        &self.build
+    }
+
+    /// Create a copy of the command with its features sorted, so that commands that only differ
+    /// in the order of their features are equal
+    pub fn normalised(&self) -> CargoCommand {
+        let mut c = self.clone();
+        c.features.sort();
+        c.features.dedup();
+        c
+    }
+
+    /// Get the names of the template arguments used in the command
+    pub fn template_args(&self) -> Vec<String> {
+        let mut names = vec![];
+        let mut c = self.as_string();
+        while let Some(start) = c.find("{{") {
+            c = c[start + 2..].to_string();
+            if let Some(end) = c.find("}}") {
+                let name = c[..end].to_string();
+                if !names.contains(&name) {
+                    names.push(name);
                 }
+                c = c[end + 2..].to_string();
             }
         }
+        names
     }
 
-    pub fn set_package(&mut self, package: &str) {
-        self.package = Some(String::from(package));
+    /// Create a copy of the command with a value substituted for a template argument
+    pub fn substitute(&self, arg: &str, value: &str) -> CargoCommand {
+        let arg = format!("{{{{{arg}}}}}");
+        let sub = |s: &String| s.replace(&arg, value);
+        let mut c = self.clone();
+        c.run = sub(&self.run);
+        c.args = self
+            .args
+            .iter()
+            .map(|(k, v)| (sub(k), sub(v)))
+            .collect::<Vec<_>>();
+        c.features = self.features.iter().map(sub).collect::<Vec<_>>();
+        if let BuildType::Profile(p) = &self.build {
+            c.build = BuildType::Profile(sub(p));
+        }
+        c.package = self.package.as_ref().map(sub);
+        c.manifest_path = self.manifest_path.as_ref().map(sub);
+        c.target = self.target.as_ref().map(sub);
+        c
     }
 
     /// Create from a string
-    pub fn from_str(c: &str, example_name: &str) -> CargoCommand {
+    ///
+    /// If a location is given, it should be the location of the first character of the command.
+    pub fn from_str(
+        c: &str,
+        example_name: &str,
+        location: Option<Location>,
+    ) -> Result<CargoCommand, Error> {
+        let word_location = |(offset, word): &(usize, String)| {
+            location
+                .as_ref()
+                .map(|l| l.part(*offset, word.chars().count()))
+        };
+        let error = |message: &str, word: &(usize, String)| {
+            Error::new(format!(
+                "{message} in command for example \"{example_name}\""
+            ))
+            .at(word_location(word))
+        };
+        let missing_value =
+            |word: &(usize, String)| error(&format!("Missing value for {}", word.1), word);
         let mut features = vec![];
+        let mut no_default_features = false;
+        let mut all_features = false;
         let mut args = vec![];
         let mut build = BuildType::Default;
-        let mut c = c.split(" ");
+        let words = split_words(c);
+        let mut c = words.iter();
         let mut package = None;
-        let run = String::from(c.next().expect("Command cannot be empty"));
+        let mut manifest_path = None;
+        let mut target = None;
+        let run = match c.next() {
+            Some((_, r)) => r.clone(),
+            None => {
+                return Err(Error::new(format!(
+                    "Command cannot be empty for example \"{example_name}\""
+                ))
+                .at(location));
+            }
+        };
         while let Some(i) = c.next() {
-            match i {
+            match i.1.as_str() {
                 "--release" => {
                     if build != BuildType::Default {
-                        panic!("Cannot set build type twice");
+                        return Err(error("Cannot set build type twice", i));
                     }
                     build = BuildType::Release;
                 }
                 "--profile" => {
                     if build != BuildType::Default {
-                        panic!("Cannot set build type twice");
+                        return Err(error("Cannot set build type twice", i));
                     }
-                    build = BuildType::Profile(String::from(
-                        c.next().expect("Profile cannot be blank"),
-                    ));
+                    build = BuildType::Profile(c.next().ok_or_else(|| missing_value(i))?.1.clone());
                 }
                 "--package" => {
                     if package.is_some() {
-                        panic!("Cannot set package twice");
+                        return Err(error("Cannot set package twice", i));
+                    }
+                    package = Some(c.next().ok_or_else(|| missing_value(i))?.1.clone());
+                }
+                "--manifest-path" => {
+                    if manifest_path.is_some() {
+                        return Err(error("Cannot set manifest path twice", i));
+                    }
+                    let v = c.next().ok_or_else(|| missing_value(i))?;
+                    manifest_path =
+                        Some(parse_string_if_quoted(&v.1).map_err(|e| e.or_at(word_location(v)))?);
+                }
+                "--target" => {
+                    if target.is_some() {
+                        return Err(error("Cannot set target twice", i));
                     }
-                    package = Some(String::from(c.next().expect("Package cannot be blank")));
+                    target = Some(c.next().ok_or_else(|| missing_value(i))?.1.clone());
                 }
                 "--features" => {
-                    // TODO: tidy this up
-                    features = parse_string_if_quoted(c.next().expect("Features cannot be blank"))
+                    let v = c.next().ok_or_else(|| missing_value(i))?;
+                    features = parse_string_if_quoted(&v.1)
+                        .map_err(|e| e.or_at(word_location(v)))?
                         .split(",")
                         .map(String::from)
                         .collect::<Vec<_>>();
                 }
+                "--no-default-features" => {
+                    no_default_features = true;
+                }
+                "--all-features" => {
+                    all_features = true;
+                }
                 _ => {
-                    args.push((
-                        String::from(i),
-                        String::from(c.next().expect("Keys and values must come in pairs")),
-                    ));
+                    let v = c
+                        .next()
+                        .ok_or_else(|| error("Keys and values must come in pairs", i))?;
+                    args.push((i.1.clone(), v.1.clone()));
                 }
             }
         }
         let example_name = String::from(example_name);
-        CargoCommand {
+        Ok(CargoCommand {
             run,
             example_name,
             args,
             features,
+            no_default_features,
+            all_features,
             available_features: vec![],
             build,
             package,
-        }
+            manifest_path,
+            target,
+            build_only: false,
+            location,
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn test_from_str_features() {
-        let c = CargoCommand::from_str("run --features \"one,two\"", "test");
+        let c = CargoCommand::from_str("run --features \"one,two\"", "test", None).unwrap();
         assert_eq!(c.features.len(), 2);
 
-        let mut c = CargoCommand::from_str("run --features \"one,two\"", "test");
-        c.set_required_features(&[String::from("one"), String::from("two")]);
+        let mut c = CargoCommand::from_str("run --features \"one,two\"", "test", None).unwrap();
+        c.set_required_features(&[String::from("one"), String::from("two")])
+            .unwrap();
         assert_eq!(c.features.len(), 2);
 
-        let mut c = CargoCommand::from_str("run", "test");
-        c.set_required_features(&[String::from("one"), String::from("two")]);
+        let mut c = CargoCommand::from_str("run", "test", None).unwrap();
+        c.set_required_features(&[String::from("one"), String::from("two")])
+            .unwrap();
         assert_eq!(c.features.len(), 2);
 
-        let mut c = CargoCommand::from_str("run --features \"one,two\"", "test");
-        c.set_required_features(&[String::from("one")]);
+        let mut c = CargoCommand::from_str("run --features \"one,two\"", "test", None).unwrap();
+        c.set_required_features(&[String::from("one")]).unwrap();
         assert_eq!(c.features.len(), 2);
     }
 
     #[test]
-    #[should_panic]
     fn test_from_str_missing_feature() {
-        let mut c = CargoCommand::from_str("run --features \"one\"", "test");
-        c.set_required_features(&[String::from("one"), String::from("two")]);
+        let mut c = CargoCommand::from_str("run --features \"one\"", "test", None).unwrap();
+        assert!(
+            c.set_required_features(&[String::from("one"), String::from("two")])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_from_str_error_location() {
+        let location = Location {
+            file: PathBuf::from("examples/test.rs"),
+            line: 1,
+            column: 5,
+            length: 30,
+        };
+        let e = CargoCommand::from_str("run --release --profile build", "test", Some(location))
+            .unwrap_err();
+        let expected = Location {
+            file: PathBuf::from("examples/test.rs"),
+            line: 1,
+            column: 19,
+            length: 9,
+        };
+        assert_eq!(
+            e,
+            Error::new("Cannot set build type twice in command for example \"test\"")
+                .at(Some(expected))
+        );
+    }
+
+    #[test]
+    fn test_from_str_feature_flags() {
+        let mut c =
+            CargoCommand::from_str("run --no-default-features --release", "test", None).unwrap();
+        assert!(c.no_default_features);
+        assert_eq!(c.build, BuildType::Release);
+        c.set_required_features(&[String::from("one")]).unwrap();
+        assert_eq!(
+            c.as_string(),
+            "cargo run --example test --features \"one\" --no-default-features --release"
+        );
+
+        let mut c = CargoCommand::from_str("run --all-features", "test", None).unwrap();
+        c.set_required_features(&[String::from("one")]).unwrap();
+        c.set_available_features(&[String::from("two")]);
+        c.add_features_if_available(&[String::from("two")]);
+        c.add_features(&[String::from("three")]);
+        c.set_default_build_type(&BuildType::Debug);
+        assert_eq!(c.as_string(), "cargo run --example test --all-features");
+    }
+
+    #[test]
+    fn test_add_features() {
+        let mut c = CargoCommand::from_str("run --release", "test", None).unwrap();
+        c.set_required_features(&[String::from("one")]).unwrap();
+        c.add_features(&[String::from("two"), String::from("one")]);
+        assert_eq!(
+            c.as_string(),
+            "cargo run --example test --features \"one,two\" --release"
+        );
+    }
+
+    #[test]
+    fn test_from_str_target() {
+        let mut c =
+            CargoCommand::from_str("mpirun -n 2 --target wasm32-wasip1", "test", None).unwrap();
+        assert_eq!(c.target(), Some("wasm32-wasip1"));
+        c.set_default_build_type(&BuildType::Release);
+        assert_eq!(
+            c.as_string(),
+            "cargo mpirun -n 2 --example test --release --target wasm32-wasip1"
+        );
+        c.set_build_only();
+        assert_eq!(
+            c.as_string(),
+            "cargo build --example test --release --target wasm32-wasip1"
+        );
+    }
+
+    #[test]
+    fn test_build_string() {
+        let mut c = CargoCommand::from_str("mpirun -n 2 --features \"one\"", "test", None).unwrap();
+        c.set_default_build_type(&BuildType::Debug);
+        assert_eq!(c.build_string(&[]), "cargo build --features \"one\"");
+        assert_eq!(
+            c.build_string(&["test", "other"]),
+            "cargo build --example test --example other --features \"one\""
+        );
+        assert!(!c.runs_binary());
+        let mut c = CargoCommand::from_str("run --release", "test", None).unwrap();
+        assert!(c.runs_binary());
+        c.set_target("wasm32-wasip1");
+        assert!(!c.runs_binary());
+    }
+
+    #[test]
+    fn test_manifest_path() {
+        let mut c = CargoCommand::from_str("run --release", "test", None).unwrap();
+        c.set_manifest_path(Path::new("crates/my crate/Cargo.toml"));
+        assert_eq!(
+            c.as_string(),
+            "cargo run --example test --release --manifest-path \"crates/my crate/Cargo.toml\""
+        );
+        assert_eq!(
+            CargoCommand::from_str(
+                "run --release --manifest-path \"crates/my crate/Cargo.toml\"",
+                "test",
+                None
+            )
+            .unwrap(),
+            c
+        );
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut c = CargoCommand::from_str(
+            "mpirun -n {{NPROCESSES}} --features \"{{FEATURE}}\" --release",
+            "test",
+            None,
+        )
+        .unwrap();
+        c.set_available_features(&[String::from("two")]);
+        assert_eq!(c.template_args(), vec!["NPROCESSES", "FEATURE"]);
+        let c = c.substitute("NPROCESSES", "4").substitute("FEATURE", "two");
+        assert!(c.template_args().is_empty());
+        assert_eq!(
+            c.as_string(),
+            "cargo mpirun -n 4 --example test --features \"two\" --release"
+        );
+    }
+
+    #[test]
+    fn test_normalised() {
+        let mut c =
+            CargoCommand::from_str("run --features \"two,one,two\" --release", "test", None)
+                .unwrap();
+        c.set_available_features(&[String::from("one"), String::from("two")]);
+        assert_eq!(
+            c.normalised().as_string(),
+            "cargo run --example test --features \"one,two\" --release"
+        );
     }
 
     #[test]
     fn test_from_str_build_type() {
-        let c = CargoCommand::from_str("run --profile build", "test");
+        let c = CargoCommand::from_str("run --profile build", "test", None).unwrap();
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
 
-        let mut c = CargoCommand::from_str("run", "test");
+        let mut c = CargoCommand::from_str("run", "test", None).unwrap();
         c.set_default_build_type(&BuildType::Profile(String::from("build")));
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
 
-        let mut c = CargoCommand::from_str("run", "test");
-        c.set_build_type(&BuildType::Profile(String::from("build")));
+        let mut c = CargoCommand::from_str("run", "test", None).unwrap();
+        c.set_build_types(&[BuildType::Profile(String::from("build"))])
+            .unwrap();
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
 
-        let mut c = CargoCommand::from_str("run --profile build", "test");
+        let mut c = CargoCommand::from_str("run --profile build", "test", None).unwrap();
         c.set_default_build_type(&BuildType::Profile(String::from("build")));
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
 
-        let mut c = CargoCommand::from_str("run --profile build", "test");
+        let mut c = CargoCommand::from_str("run --profile build", "test", None).unwrap();
         c.set_default_build_type(&BuildType::Debug);
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
     }
 
     #[test]
-    #[should_panic]
+    fn test_is_defined() {
+        let profiles = [String::from("release"), String::from("fast")];
+        assert!(BuildType::Debug.is_defined(&profiles));
+        assert!(BuildType::from_str("fast").is_defined(&profiles));
+        assert!(!BuildType::from_str("relase").is_defined(&profiles));
+        assert!(BuildType::from_str("{{PROFILE}}").is_defined(&profiles));
+    }
+
+    #[test]
+    fn test_force_build_type() {
+        let mut c = CargoCommand::from_str("run --profile build", "test", None).unwrap();
+        c.force_build_type(&BuildType::Debug);
+        assert_eq!(c.as_string(), "cargo run --example test");
+    }
+
+    #[test]
     fn test_from_str_incompatible_build_type() {
-        let mut c = CargoCommand::from_str("run --profile build", "test");
-        c.set_build_type(&BuildType::Debug);
+        let mut c = CargoCommand::from_str("run --profile build", "test", None).unwrap();
+        assert!(c.set_build_types(&[BuildType::Debug]).is_err());
+        assert_eq!(c.build, BuildType::Profile(String::from("build")));
+        assert!(
+            c.set_build_types(&[BuildType::Debug, BuildType::Release])
+                .is_err()
+        );
+        c.set_build_types(&[BuildType::Debug, BuildType::Profile(String::from("build"))])
+            .unwrap();
         assert_eq!(c.build, BuildType::Profile(String::from("build")));
+
+        let mut c = CargoCommand::from_str("run", "test", None).unwrap();
+        c.set_build_types(&[BuildType::Debug, BuildType::Release])
+            .unwrap();
+        assert_eq!(c.build, BuildType::Default);
     }
 }
@@ -0,0 +1,178 @@
+//! Errors
+
+use std::{
+    env::current_dir,
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// A location in a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// The file
+    pub file: PathBuf,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// The column number, starting at 1
+    pub column: usize,
+    /// The number of characters to highlight
+    pub length: usize,
+}
+
+impl Location {
+    /// Create the location of a range of bytes in the contents of a file
+    pub fn from_span(file: &Path, contents: &str, span: Range<usize>) -> Self {
+        let line_start = contents[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            file: PathBuf::from(file),
+            line: contents[..span.start].matches('\n').count() + 1,
+            column: contents[line_start..span.start].chars().count() + 1,
+            length: contents[span]
+                .lines()
+                .next()
+                .map_or(0, |l| l.chars().count())
+                .max(1),
+        }
+    }
+
+    /// Get the location of some characters inside this location
+    pub fn part(&self, offset: usize, length: usize) -> Self {
+        Self {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column + offset,
+            length: length.max(1),
+        }
+    }
+
+    /// Write the location and the line of the file that it points to
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = match current_dir() {
+            Ok(d) => self.file.strip_prefix(d).unwrap_or(&self.file),
+            Err(_) => &self.file,
+        };
+        let line_number = self.line.to_string();
+        let pad = " ".repeat(line_number.len());
+        writeln!(
+            f,
+            "{pad}--> {}:{}:{}",
+            file.display(),
+            self.line,
+            self.column
+        )?;
+        if let Ok(contents) = fs::read_to_string(&self.file)
+            && let Some(line) = contents.lines().nth(self.line - 1)
+        {
+            writeln!(f, "{pad} |")?;
+            writeln!(f, "{line_number} | {line}")?;
+            writeln!(
+                f,
+                "{pad} | {}{}",
+                " ".repeat(self.column - 1),
+                "^".repeat(self.length)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// An error in the configuration of the examples
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    message: String,
+    location: Option<Location>,
+    notes: Vec<(String, Option<Location>)>,
+    warning: bool,
+}
+
+impl Error {
+    /// Create new
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            location: None,
+            notes: vec![],
+            warning: false,
+        }
+    }
+
+    /// Create a warning
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            warning: true,
+            ..Self::new(message)
+        }
+    }
+
+    /// Is this a warning?
+    pub fn is_warning(&self) -> bool {
+        self.warning
+    }
+
+    /// Set the location that caused the error
+    pub fn at(mut self, location: Option<Location>) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Set the location that caused the error if it has not already been set
+    pub fn or_at(mut self, location: Option<Location>) -> Self {
+        if self.location.is_none() {
+            self.location = location;
+        }
+        self
+    }
+
+    /// Add a note to the error
+    pub fn note(mut self, message: impl Into<String>, location: Option<Location>) -> Self {
+        self.notes.push((message.into(), location));
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.warning {
+            writeln!(f, "warning: {}", self.message)?;
+        } else {
+            writeln!(f, "error: {}", self.message)?;
+        }
+        if let Some(l) = &self.location {
+            l.write(f)?;
+        }
+        for (message, location) in &self.notes {
+            writeln!(f, "note: {message}")?;
+            if let Some(l) = location {
+                l.write(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_span() {
+        let contents = "[package]\nname = \"test\"\n";
+        let l = Location::from_span(Path::new("Cargo.toml"), contents, 17..23);
+        assert_eq!(l.line, 2);
+        assert_eq!(l.column, 8);
+        assert_eq!(l.length, 6);
+        let l = l.part(1, 4);
+        assert_eq!(l.column, 9);
+        assert_eq!(l.length, 4);
+    }
+
+    #[test]
+    fn test_display() {
+        let e = Error::new("Something went wrong").note("Try again", None);
+        assert_eq!(
+            e.to_string(),
+            "error: Something went wrong\nnote: Try again\n"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Generate combinations of features to run examples with
+
+use crate::command_line::FeatureCombinations;
+
+/// Get the combinations of features that an example is run with
+///
+/// `candidates` are the features that are combined. The combinations are used with
+/// `--no-default-features`, so `default` is combined like any other feature. The `required`
+/// features of the example are enabled in every run, so they are not combined. For the powerset,
+/// `depth` is the maximum number of features in each combination.
+///
+/// Combinations that enable more than one feature from a group of mutually exclusive features are
+/// removed once the features of each run are known, see [`conflicts`].
+pub fn combinations(
+    mode: FeatureCombinations,
+    candidates: &[String],
+    required: &[String],
+    depth: Option<usize>,
+) -> Vec<Vec<String>> {
+    let candidates = candidates
+        .iter()
+        .filter(|f| !required.contains(f))
+        .collect::<Vec<_>>();
+    let depth = match mode {
+        FeatureCombinations::EachFeature => 1,
+        FeatureCombinations::Powerset => depth.unwrap_or(candidates.len()),
+    };
+    let mut combinations = vec![vec![]];
+    let mut previous = vec![vec![]];
+    for _ in 0..depth {
+        let mut next = vec![];
+        for c in &previous {
+            // Only extend with features that come after the last feature, so that each
+            // combination is generated once
+            let start = c.last().map_or(0, |last| last + 1);
+            for i in start..candidates.len() {
+                let mut c = c.clone();
+                c.push(i);
+                next.push(c);
+            }
+        }
+        combinations.extend(next.iter().cloned());
+        previous = next;
+    }
+    combinations
+        .into_iter()
+        .map(|c| {
+            c.into_iter()
+                .map(|i| candidates[i].clone())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Do the features passed to cargo enable more than one feature from a mutually exclusive group?
+///
+/// Each entry of `features` may be a list of features separated by commas or spaces, as a value
+/// substituted for a template argument can contain several features.
+pub fn conflicts(features: &[String], groups: &[Vec<String>]) -> bool {
+    let features = features
+        .iter()
+        .flat_map(|f| f.split([',', ' ']))
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>();
+    groups
+        .iter()
+        .any(|g| g.iter().filter(|f| features.contains(&f.as_str())).count() > 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_strings(features: &[&str]) -> Vec<String> {
+        features
+            .iter()
+            .map(|f| String::from(*f))
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_combinations() {
+        let candidates = to_strings(&["default", "a", "b", "c"]);
+        assert_eq!(
+            combinations(FeatureCombinations::EachFeature, &candidates, &[], None),
+            vec![
+                vec![],
+                to_strings(&["default"]),
+                to_strings(&["a"]),
+                to_strings(&["b"]),
+                to_strings(&["c"])
+            ]
+        );
+        assert_eq!(
+            combinations(FeatureCombinations::Powerset, &candidates, &[], None).len(),
+            16
+        );
+        assert_eq!(
+            combinations(
+                FeatureCombinations::Powerset,
+                &candidates[1..],
+                &to_strings(&["a"]),
+                Some(1)
+            ),
+            vec![vec![], to_strings(&["b"]), to_strings(&["c"])]
+        );
+        assert_eq!(
+            combinations(
+                FeatureCombinations::Powerset,
+                &candidates[1..],
+                &[],
+                Some(2)
+            ),
+            vec![
+                vec![],
+                to_strings(&["a"]),
+                to_strings(&["b"]),
+                to_strings(&["c"]),
+                to_strings(&["a", "b"]),
+                to_strings(&["a", "c"]),
+                to_strings(&["b", "c"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let groups = [to_strings(&["b", "c"])];
+        assert!(!conflicts(&to_strings(&["a", "b"]), &groups));
+        assert!(conflicts(&to_strings(&["c", "b"]), &groups));
+        assert!(conflicts(&to_strings(&["a,c", "b"]), &groups));
+        assert!(conflicts(&to_strings(&["c b"]), &groups));
+        assert!(!conflicts(&to_strings(&["bc"]), &groups));
+    }
+}
@@ -6,25 +6,86 @@
 #![cfg_attr(feature = "strict", deny(warnings), deny(unused_crate_dependencies))]
 #![warn(missing_docs)]
 
+mod baseline;
+mod bench;
+mod build;
+mod cache;
 mod cargo_toml;
+mod check;
 mod command_line;
 mod commands;
+mod error;
+mod features;
 mod parsing;
+mod plan;
 mod rust_file;
-use cargo_toml::join;
+mod state;
+#[cfg(test)]
+mod temp_dir;
+mod usage;
+use build::Build;
+use cache::Cache;
+use command_line::{Mode, SpecialArgs};
 use commands::{BuildType, CargoCommand};
+use error::Error;
+use plan::Run;
+use state::State;
+use usage::Usage;
 
 use std::{
-    collections::HashMap,
-    fs,
-    path::Path,
+    cmp::Reverse,
+    collections::BTreeMap,
     process::{Command, ExitCode},
+    time::{Duration, Instant},
 };
 
+/// The outcome of a run
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    /// The example ran successfully
+    Passed,
+    /// The example failed but then ran successfully when it was retried
+    Flaky,
+    /// The example was built successfully but not run, as its target is build-only
+    Built,
+    /// The example was built but exited with an error when run
+    Failed,
+    /// The example failed to build
+    BuildFailed,
+    /// The run was skipped because its inputs have not changed since it last succeeded
+    Skipped,
+    /// The run was never started because too many other runs failed
+    NotStarted,
+}
+
+/// A run and its outcome
+struct RunResult {
+    run: Run,
+    outcome: Outcome,
+    /// The time and resources used, if the example was run
+    usage: Option<Usage>,
+    /// The wall-clock times of the measured runs in bench mode, or otherwise the wall-clock time
+    /// of the attempt that succeeded
+    samples: Vec<Duration>,
+}
+
 /// Number of passing and failing examples
+///
+/// Examples that fail to build are counted separately from examples that build but exit with
+/// an error when run.
 struct RunOutcomes {
     passes: usize,
+    flaky: usize,
+    built: usize,
     fails: usize,
+    build_fails: usize,
+    skipped: usize,
+    not_started: usize,
+    /// The build commands that failed and the first compiler error that each reported, with one
+    /// command for each example that failed to build
+    build_errors: Vec<(String, Option<String>)>,
+    /// Each run and its outcome
+    results: Vec<RunResult>,
 }
 
 impl RunOutcomes {
@@ -32,41 +93,46 @@ impl RunOutcomes {
     fn new() -> Self {
         Self {
             passes: 0,
+            flaky: 0,
+            built: 0,
             fails: 0,
+            build_fails: 0,
+            skipped: 0,
+            not_started: 0,
+            build_errors: vec![],
+            results: vec![],
         }
     }
 
-    /// Add
-    fn add(&mut self, other: &RunOutcomes) {
-        self.passes += other.passes;
-        self.fails += other.fails;
+    /// Have at least `max_failures` runs failed?
+    fn reached(&self, max_failures: Option<usize>) -> bool {
+        max_failures.is_some_and(|m| self.fails + self.build_fails >= m)
     }
-}
 
-/// Get example command for a file
-fn get_example_command(dir: &impl AsRef<Path>, eg: &str) -> CargoCommand {
-    let file_command = rust_file::load_command(dir, eg);
-    let cargo_toml_command = cargo_toml::load_command(dir, eg);
-
-    // Return command
-    if let Some(c) = file_command {
-        if let Some(c2) = cargo_toml_command
-            && c != c2
-        {
-            panic!("Commands set in file and Cargo.toml do not match for example \"{eg}\"");
+    /// Add the outcome of a run
+    fn add(&mut self, run: Run, outcome: Outcome, usage: Option<Usage>, samples: Vec<Duration>) {
+        match outcome {
+            Outcome::Passed => self.passes += 1,
+            Outcome::Flaky => self.flaky += 1,
+            Outcome::Built => self.built += 1,
+            Outcome::Failed => self.fails += 1,
+            Outcome::BuildFailed => self.build_fails += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::NotStarted => self.not_started += 1,
         }
-        c
-    } else if let Some(c) = cargo_toml_command {
-        c
-    } else {
-        CargoCommand::new(String::from(eg))
+        self.results.push(RunResult {
+            run,
+            outcome,
+            usage,
+            samples,
+        });
     }
 }
 
-/// Run an example
-fn run_example(example: &str) -> Result<(), &str> {
+/// Create a command that runs a string in the system's shell
+fn shell(command: &str) -> Command {
     #[cfg(target_os = "windows")]
-    let mut shell = Command::new("cmd /C");
+    let mut shell = Command::new("cmd");
     #[cfg(target_os = "windows")]
     shell.arg("/C");
 
@@ -74,99 +140,408 @@ fn run_example(example: &str) -> Result<(), &str> {
     let mut shell = Command::new("sh");
     #[cfg(not(target_os = "windows"))]
     shell.arg("-c");
-    shell.arg(example);
+    shell.arg(command);
+    shell
+}
 
-    let mut child = shell.spawn().expect("Error initialising example run");
-    match child.wait().expect("Error running example").code() {
-        Some(0) => Ok(()),
-        _ => Err("Example run failed"),
+/// Create the command that runs an example once it has been built
+///
+/// The binaries of plain `cargo run` commands are run directly, so that the time and resources
+/// used by the example are measured rather than those used by cargo. Other commands, and examples
+/// that may need a runner, are run through cargo, which only needs to run the example as it has
+/// already been built.
+fn example_command(r: &Run, build: &Build, runner: bool) -> Command {
+    match build.executables.get(r.command.example_name()) {
+        Some(e) if r.command.runs_binary() && !runner => {
+            let mut c = Command::new(e);
+            if let Ok(d) = std::path::absolute(&r.dir) {
+                c.env("CARGO_MANIFEST_DIR", d);
+            }
+            c
+        }
+        _ => shell(&r.command.as_string()),
     }
 }
 
-/// Run all examples in a directory
-fn run_all_examples(dir: &Path, package: Option<String>) -> RunOutcomes {
-    let mut outcomes = RunOutcomes::new();
+/// Run an example, returning whether the run was successful and the resources it used
+fn run_example(mut example: Command) -> Result<(bool, Usage), Error> {
+    let start = Instant::now();
+    let mut child = example
+        .spawn()
+        .map_err(|e| Error::new(format!("Error initialising example run: {e}")))?;
+    usage::wait(&mut child, start).map_err(|e| Error::new(format!("Error running example: {e}")))
+}
 
-    if let Some(w) = cargo_toml::get_workspace(&dir) {
-        for c in w {
-            outcomes.add(&run_all_examples(&join(&dir, &c), Some(c)));
+/// Run an example, retrying it if it fails
+///
+/// The usage returned is the total for all attempts, and if an attempt succeeded its wall-clock
+/// time is also returned.
+fn run_with_retries(
+    r: &Run,
+    example: impl Fn() -> Command,
+) -> (Outcome, Option<Usage>, Vec<Duration>) {
+    let c = r.description();
+    let mut total_usage: Option<Usage> = None;
+    for attempt in 0..=r.retries {
+        println!();
+        if attempt == 0 {
+            println!("RUNNING {c}");
+        } else {
+            println!(
+                "RETRYING {c} (attempt {} of {})",
+                attempt + 1,
+                r.retries + 1
+            );
+        }
+        println!();
+        match run_example(example()) {
+            Ok((success, usage)) => {
+                println!();
+                println!("FINISHED in {}", usage.summary());
+                let wall = usage.wall;
+                match &mut total_usage {
+                    Some(u) => u.add(&usage),
+                    None => total_usage = Some(usage),
+                }
+                if success {
+                    let outcome = if attempt == 0 {
+                        Outcome::Passed
+                    } else {
+                        Outcome::Flaky
+                    };
+                    return (outcome, total_usage, vec![wall]);
+                }
+            }
+            Err(e) => {
+                eprint!("{e}");
+            }
         }
     }
+    (Outcome::Failed, total_usage, vec![])
+}
 
-    let default_build = cargo_toml::get_default_build(&dir);
-
-    if !join(&dir, "examples").is_dir() {
-        return outcomes;
+/// Run an example repeatedly, returning the wall-clock times of the measured runs
+///
+/// The example is first run `warmup` times without being measured. If any run fails, the
+/// example is not run again.
+fn bench_example(
+    r: &Run,
+    example: impl Fn() -> Command,
+    warmup: usize,
+    repeats: usize,
+) -> (Outcome, Option<Usage>, Vec<Duration>) {
+    let c = r.description();
+    let mut total_usage: Option<Usage> = None;
+    let mut samples = vec![];
+    for i in 0..warmup + repeats {
+        println!();
+        if i < warmup {
+            println!("WARMING UP {c} ({} of {warmup})", i + 1);
+        } else {
+            println!("BENCHMARKING {c} ({} of {repeats})", i - warmup + 1);
+        }
+        println!();
+        match run_example(example()) {
+            Ok((success, usage)) => {
+                println!();
+                println!("FINISHED in {}", usage.summary());
+                if i >= warmup {
+                    samples.push(usage.wall);
+                }
+                match &mut total_usage {
+                    Some(u) => u.add(&usage),
+                    None => total_usage = Some(usage),
+                }
+                if !success {
+                    return (Outcome::Failed, total_usage, samples);
+                }
+            }
+            Err(e) => {
+                eprint!("{e}");
+                return (Outcome::Failed, total_usage, samples);
+            }
+        }
     }
+    (Outcome::Passed, total_usage, samples)
+}
 
-    let special_args = command_line::load_special_args();
+/// Run the examples in a group once the group has been built
+///
+/// If the build failed, the examples whose binaries were built can still be run. The examples that
+/// failed to build are recorded first, so that they count towards `max_failures` before any
+/// examples in the group are started.
+fn run_group(
+    outcomes: &mut RunOutcomes,
+    runs: Vec<Run>,
+    build: &Build,
+    max_failures: Option<usize>,
+    mut run: impl FnMut(&Run) -> (Outcome, Option<Usage>, Vec<Duration>),
+) {
+    let (built, not_built) = runs.into_iter().partition::<Vec<_>, _>(|r| {
+        build.success || build.executables.contains_key(r.command.example_name())
+    });
+    for r in not_built {
+        let name = r.command.example_name();
+        let command = r.command.build_string(&[name]);
+        if !outcomes.build_errors.iter().any(|(c, _)| *c == command) {
+            let error = build.errors.get(name).or(build.first_error.as_ref());
+            outcomes.build_errors.push((command, error.cloned()));
+        }
+        outcomes.add(r, Outcome::BuildFailed, None, vec![]);
+    }
+    for r in built {
+        if outcomes.reached(max_failures) {
+            outcomes.add(r, Outcome::NotStarted, None, vec![]);
+        } else if r.command.is_build_only() {
+            outcomes.add(r, Outcome::Built, None, vec![]);
+        } else {
+            let (outcome, usage, samples) = run(&r);
+            outcomes.add(r, outcome, usage, samples);
+        }
+    }
+}
 
-    // Load all template examples from files
-    let mut examples = vec![];
-    for file in fs::read_dir(join(&dir, "examples")).expect("Could not find examples directory") {
-        let file = file.expect("Error reading examples directory").path();
-        if let Some(e) = file.extension()
-            && e == "rs"
-        {
-            let file_stem = file
-                .file_stem()
-                .expect("Error parsing file name")
-                .to_str()
-                .expect("Error parsing file name");
-
-            let mut c = get_example_command(&dir, file_stem);
-            c.set_default_build_type(&default_build);
-            c.set_required_features(&cargo_toml::load_required_features(&dir, file_stem));
-            c.set_available_features(&cargo_toml::load_available_features(&dir));
-            c.add_features_if_available(&special_args.features);
-            if let Some(p) = &package {
-                c.set_package(p);
-            }
-            examples.push(c.as_string());
-        }
-    }
-
-    // Substitute all template arguments
-    let mut template_args = HashMap::new();
-    cargo_toml::load_args(&dir, &mut template_args);
-    command_line::load_args(&mut template_args);
-
-    for (a, options) in &template_args {
-        let mut new_examples = vec![];
-        let a = format!("{{{{{a}}}}}");
-        for c in &examples {
-            if c.contains(&a) {
-                for o in options {
-                    new_examples.push(c.replace(&a, o))
+/// Build and run examples
+///
+/// Runs that use the same package, features, profile and target are built together, and each
+/// group is built immediately before it is run so that its binaries are not replaced by a
+/// build with different options.
+///
+/// If a cache is given, runs whose inputs have not changed since they last succeeded are skipped,
+/// and the cache is updated with the outcomes of the runs. Once `max_failures` runs have failed,
+/// no more runs are started. In bench mode, each example is run repeatedly and timed.
+fn run_all_examples(runs: Vec<Run>, cache: &mut Option<Cache>, args: &SpecialArgs) -> RunOutcomes {
+    let mut outcomes = RunOutcomes::new();
+    let runs = match cache {
+        Some(c) => {
+            let (skipped, runs) = runs.into_iter().partition::<Vec<_>, _>(|r| c.is_fresh(r));
+            for r in skipped {
+                outcomes.add(r, Outcome::Skipped, None, vec![]);
+            }
+            runs
+        }
+        None => runs,
+    };
+    let runner = cargo_toml::runner_configured();
+    for group in plan::group_runs(runs) {
+        if outcomes.reached(args.max_failures) {
+            for r in group.runs {
+                outcomes.add(r, Outcome::NotStarted, None, vec![]);
+            }
+            continue;
+        }
+        println!();
+        println!("BUILDING {}", group.command);
+        println!();
+        let build = match build::build(&group.command) {
+            Ok(b) => b,
+            Err(e) => {
+                eprint!("{e}");
+                outcomes.build_errors.push((group.command, None));
+                for r in group.runs {
+                    outcomes.add(r, Outcome::BuildFailed, None, vec![]);
                 }
+                continue;
+            }
+        };
+        run_group(&mut outcomes, group.runs, &build, args.max_failures, |r| {
+            let example = || example_command(r, &build, runner);
+            if args.mode == Mode::Bench {
+                bench_example(r, example, args.warmup, args.repeats)
             } else {
-                new_examples.push(c.clone());
+                run_with_retries(r, example)
+            }
+        });
+    }
+    if let Some(c) = cache {
+        for r in &outcomes.results {
+            match r.outcome {
+                Outcome::Passed | Outcome::Flaky | Outcome::Built => c.record_success(&r.run),
+                Outcome::Failed | Outcome::BuildFailed => c.record_failure(&r.run),
+                Outcome::Skipped | Outcome::NotStarted => {}
             }
         }
-        examples = new_examples;
+        if let Err(e) = c.save() {
+            eprint!("{e}");
+        }
     }
+    outcomes
+}
 
-    // Run examples
-    for c in &examples {
-        println!();
-        println!("RUNNING {c}");
+/// Print the total time and resources used by the runs, and the slowest runs
+fn print_usage(outcomes: &RunOutcomes, slowest: Option<usize>) {
+    let mut runs = outcomes
+        .results
+        .iter()
+        .filter_map(|r| r.usage.as_ref().map(|u| (r, u)))
+        .collect::<Vec<_>>();
+    if runs.is_empty() {
+        return;
+    }
+    let mut total = runs[0].1.clone();
+    for (_, u) in &runs[1..] {
+        total.add(u);
+    }
+    println!("Total time running examples: {}", total.summary());
+
+    if let Some(n) = slowest {
+        runs.sort_by_key(|(_, u)| Reverse(u.wall));
         println!();
-        if run_example(c).is_err() {
-            outcomes.fails += 1;
-        } else {
-            outcomes.passes += 1;
+        println!("SLOWEST RUNS");
+        println!(
+            "{:>9} {:>9} {:>9} {:>10}  command",
+            "wall", "user", "system", "max RSS"
+        );
+        let optional = |t: Option<String>| t.unwrap_or_else(|| String::from("-"));
+        for (r, u) in runs.iter().take(n) {
+            println!(
+                "{:>9} {:>9} {:>9} {:>10}  {}",
+                usage::format_duration(u.wall),
+                optional(u.user.map(usage::format_duration)),
+                optional(u.system.map(usage::format_duration)),
+                optional(u.max_rss.map(usage::format_bytes)),
+                r.run.description()
+            );
         }
     }
-    outcomes
+}
+
+/// Check the configuration of the examples and report any problems
+fn check_all_examples(args: &SpecialArgs, cli_args: &BTreeMap<String, Vec<String>>) -> ExitCode {
+    let problems = match cargo_toml::find() {
+        Ok(dir) => check::check(&dir, args, cli_args),
+        Err(e) => vec![e],
+    };
+    for p in &problems {
+        eprint!("{p}");
+    }
+    let warnings = problems.iter().filter(|p| p.is_warning()).count();
+    let errors = problems.len() - warnings;
+
+    println!();
+    println!("SUMMARY");
+    if problems.is_empty() {
+        println!("No problems found.");
+        ExitCode::SUCCESS
+    } else {
+        println!(
+            "{errors} error{} and {warnings} warning{} found.",
+            if errors == 1 { "" } else { "s" },
+            if warnings == 1 { "" } else { "s" }
+        );
+        ExitCode::FAILURE
+    }
 }
 
 fn main() -> ExitCode {
-    let dir = cargo_toml::find();
-    let outcomes = run_all_examples(&dir, None);
+    let (args, cli_args) = match command_line::load() {
+        Ok(input) => input,
+        Err(e) => {
+            eprint!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.mode == Mode::Check {
+        return check_all_examples(&args, &cli_args);
+    }
+
+    let dir = match cargo_toml::find() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprint!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+    let (mut runs, duplicates) = match plan::load_runs(&dir, &args, &cli_args) {
+        Ok((runs, duplicates, warnings)) => {
+            for w in warnings {
+                eprint!("{w}");
+            }
+            (runs, duplicates)
+        }
+        Err(errors) => {
+            for e in errors {
+                eprint!("{e}");
+            }
+            return ExitCode::from(2);
+        }
+    };
+    let mut state = match State::load(&dir) {
+        Ok(s) => s,
+        Err(e) => {
+            eprint!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.rerun_failed {
+        runs.retain(|r| state.failed(r));
+        if runs.is_empty() {
+            println!("No runs failed last time.");
+            return ExitCode::SUCCESS;
+        }
+    } else {
+        state.clear();
+    }
+    let baseline = match &args.baseline {
+        Some(name) => match baseline::load(&dir, name) {
+            Ok(b) => Some(b),
+            Err(e) => {
+                eprint!("{e}");
+                return ExitCode::from(2);
+            }
+        },
+        None => None,
+    };
+    let mut cache = if args.incremental {
+        match Cache::load(&dir) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                eprint!("{e}");
+                return ExitCode::from(2);
+            }
+        }
+    } else {
+        None
+    };
+    let outcomes = run_all_examples(runs, &mut cache, &args);
+    for r in &outcomes.results {
+        state.record(&r.run, r.outcome, r.usage.as_ref());
+    }
+    if let Err(e) = state.save() {
+        eprint!("{e}");
+    }
+    if let Some(name) = &args.save_baseline
+        && let Err(e) = baseline::save(&dir, name, &outcomes.results)
+    {
+        eprint!("{e}");
+    }
 
     println!();
     println!("SUMMARY");
-    if outcomes.passes + outcomes.fails == 0 {
+    if duplicates > 0 {
+        println!(
+            "{duplicates} duplicate run{} skipped.",
+            if duplicates == 1 { " was" } else { "s were" }
+        );
+    }
+    if outcomes.skipped > 0 {
+        println!(
+            "{} example{} skipped as {} not changed since {} last ran successfully.",
+            outcomes.skipped,
+            if outcomes.skipped == 1 {
+                " was"
+            } else {
+                "s were"
+            },
+            if outcomes.skipped == 1 {
+                "it has"
+            } else {
+                "they have"
+            },
+            if outcomes.skipped == 1 { "it" } else { "they" }
+        );
+    }
+    if outcomes.results.is_empty() {
         println!("Couldn't find any examples to run.");
         ExitCode::FAILURE
     } else {
@@ -175,15 +550,179 @@ fn main() -> ExitCode {
             outcomes.passes,
             if outcomes.passes == 1 { "" } else { "s" }
         );
-        if outcomes.fails == 0 {
-            ExitCode::SUCCESS
-        } else {
+        if outcomes.built > 0 {
+            println!(
+                "{} example{} built but not run.",
+                outcomes.built,
+                if outcomes.built == 1 {
+                    " was"
+                } else {
+                    "s were"
+                }
+            );
+        }
+        if outcomes.flaky > 0 {
+            println!(
+                "{} example{} flaky and only ran successfully after retrying:",
+                outcomes.flaky,
+                if outcomes.flaky == 1 {
+                    " was"
+                } else {
+                    "s were"
+                }
+            );
+            for r in &outcomes.results {
+                if r.outcome == Outcome::Flaky {
+                    println!("  {}", r.run.description());
+                }
+            }
+        }
+        if outcomes.build_fails > 0 {
+            println!(
+                "{} example{} failed to build:",
+                outcomes.build_fails,
+                if outcomes.build_fails == 1 { "" } else { "s" }
+            );
+            for (command, error) in &outcomes.build_errors {
+                println!("  {command}");
+                if let Some(e) = error {
+                    println!("    {e}");
+                }
+            }
+        }
+        if outcomes.fails > 0 {
             println!(
                 "{} example{} encountered errors.",
                 outcomes.fails,
                 if outcomes.fails == 1 { "" } else { "s" }
             );
+        }
+        if outcomes.not_started > 0 {
+            println!(
+                "{} example{} not started as too many examples failed:",
+                outcomes.not_started,
+                if outcomes.not_started == 1 {
+                    " was"
+                } else {
+                    "s were"
+                }
+            );
+            for r in &outcomes.results {
+                if r.outcome == Outcome::NotStarted {
+                    println!("  {}", r.run.description());
+                }
+            }
+        }
+        print_usage(&outcomes, args.slowest);
+        if args.mode == Mode::Bench {
+            let runs = bench::run_rows(&outcomes.results);
+            let bindings = bench::binding_rows(&outcomes.results);
+            bench::print_table("BENCHMARKS", &runs);
+            bench::print_table("BENCHMARKS BY TEMPLATE ARGUMENT", &bindings);
+            let mut exports = vec![];
+            if let Some(path) = &args.export_csv {
+                exports.push(bench::export(path, &bench::to_csv(&runs, &bindings)));
+            }
+            if let Some(path) = &args.export_json {
+                let json = bench::to_json(&runs, &bindings);
+                exports.push(bench::export(path, &format!("{json:#}\n")));
+            }
+            for e in exports.iter().filter_map(|e| e.as_ref().err()) {
+                eprint!("{e}");
+            }
+            if exports.iter().any(|e| e.is_err()) {
+                return ExitCode::FAILURE;
+            }
+        }
+        let mut regressions = 0;
+        if let Some(b) = &baseline {
+            let changes = b.compare(&outcomes.results, args.threshold);
+            baseline::print_changes(&b.name, &changes);
+            regressions = changes.iter().filter(|c| c.is_regression()).count();
+        }
+        if outcomes.fails + outcomes.build_fails == 0
+            && (regressions == 0 || !args.fail_on_regression)
+        {
+            ExitCode::SUCCESS
+        } else {
             ExitCode::FAILURE
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    fn run(example: &str) -> Run {
+        Run {
+            dir: PathBuf::from("/crate"),
+            example: String::from(example),
+            file: PathBuf::from(format!("/crate/examples/{example}.rs")),
+            bindings: vec![],
+            command: CargoCommand::from_str("run --release", example, None).unwrap(),
+            feature_set: None,
+            retries: 0,
+        }
+    }
+
+    /// Run a group in which `d` failed to build and `a` fails when run
+    fn run_group_with(max_failures: Option<usize>) -> (RunOutcomes, Vec<String>) {
+        let build = Build {
+            success: false,
+            executables: ["a", "b", "c"]
+                .iter()
+                .map(|e| (e.to_string(), PathBuf::from(e)))
+                .collect(),
+            first_error: Some(String::from("error: first")),
+            errors: BTreeMap::from([(String::from("d"), String::from("error: in d"))]),
+        };
+        let mut outcomes = RunOutcomes::new();
+        let mut started = vec![];
+        run_group(
+            &mut outcomes,
+            ["a", "b", "c", "d"].iter().map(|e| run(e)).collect(),
+            &build,
+            max_failures,
+            |r| {
+                started.push(r.example.clone());
+                let outcome = if r.example == "a" {
+                    Outcome::Failed
+                } else {
+                    Outcome::Passed
+                };
+                (outcome, None, vec![])
+            },
+        );
+        (outcomes, started)
+    }
+
+    #[test]
+    fn test_run_group_max_failures() {
+        // Examples that failed to build count towards the maximum before any example is started
+        let (outcomes, started) = run_group_with(Some(1));
+        assert!(started.is_empty());
+        assert_eq!(outcomes.build_fails, 1);
+        assert_eq!(outcomes.not_started, 3);
+        assert_eq!(
+            outcomes.build_errors,
+            [(
+                String::from("cargo build --example d --release"),
+                Some(String::from("error: in d"))
+            )]
+        );
+
+        let (outcomes, started) = run_group_with(Some(2));
+        assert_eq!(started, ["a"]);
+        assert_eq!(outcomes.fails, 1);
+        assert_eq!(outcomes.not_started, 2);
+
+        let (outcomes, started) = run_group_with(None);
+        assert_eq!(started, ["a", "b", "c"]);
+        assert_eq!(outcomes.passes, 2);
+        assert_eq!(outcomes.fails, 1);
+        assert_eq!(outcomes.build_fails, 1);
+        assert_eq!(outcomes.not_started, 0);
+    }
+}
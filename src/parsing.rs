@@ -1,22 +1,101 @@
 //! String parsing
 
+use crate::error::Error;
+
 /// If a string starts with a quote, parse what's inside the quotes
-pub fn parse_string_if_quoted(s: &str) -> String {
-    if s.starts_with("\"") || s.starts_with("'") {
-        assert_eq!(s[0..1], s[s.len() - 1..s.len()]);
+pub fn parse_string_if_quoted(s: &str) -> Result<String, Error> {
+    if let Some(quote) = s.chars().next()
+        && (quote == '"' || quote == '\'')
+    {
+        if s.len() < 2 || !s.ends_with(quote) {
+            return Err(Error::new(format!("Unterminated string: {s}")));
+        }
         let mut s = s[1..s.len() - 1].chars();
         let mut output = String::new();
         while let Some(c) = s.next() {
             if c == '\\' {
-                output.push(s.next().expect("String cannot end with a backslash"));
+                output.push(
+                    s.next()
+                        .ok_or_else(|| Error::new("String cannot end with a backslash"))?,
+                );
             } else {
                 output.push(c);
             }
         }
-        output
+        Ok(output)
     } else {
-        s.to_string()
+        Ok(s.to_string())
+    }
+}
+
+/// Split a command into space-separated words, keeping quoted strings together
+///
+/// The position of the first character of each word is returned alongside the word.
+pub fn split_words(s: &str) -> Vec<(usize, String)> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = s.chars().enumerate();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) => {
+                word.push(c);
+                if c == '\\' {
+                    if let Some((_, c)) = chars.next() {
+                        word.push(c);
+                    }
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                if c == ' ' {
+                    if !word.is_empty() {
+                        words.push((start, word));
+                        word = String::new();
+                    }
+                } else {
+                    if word.is_empty() {
+                        start = i;
+                    }
+                    if c == '"' || c == '\'' {
+                        quote = Some(c);
+                    }
+                    word.push(c);
+                }
+            }
+        }
     }
+    if !word.is_empty() {
+        words.push((start, word));
+    }
+    words
+}
+
+/// Check if a name matches a glob pattern containing `*` and `?` wildcards
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // Position in the pattern of the last * and position in the name that it was matched at
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 #[cfg(test)]
@@ -25,9 +104,42 @@ mod test {
 
     #[test]
     fn test_parse_if_quoted() {
-        assert_eq!(parse_string_if_quoted("\"test\""), "test");
-        assert_eq!(parse_string_if_quoted("\"test\\\\\""), "test\\");
-        assert_eq!(parse_string_if_quoted("\"test\\\"\""), "test\"");
-        assert_eq!(parse_string_if_quoted("test\\\""), "test\\\"");
+        assert_eq!(parse_string_if_quoted("\"test\"").unwrap(), "test");
+        assert_eq!(parse_string_if_quoted("\"test\\\\\"").unwrap(), "test\\");
+        assert_eq!(parse_string_if_quoted("\"test\\\"\"").unwrap(), "test\"");
+        assert_eq!(parse_string_if_quoted("test\\\"").unwrap(), "test\\\"");
+        assert!(parse_string_if_quoted("\"test").is_err());
+        assert!(parse_string_if_quoted("\"é").is_err());
+        assert_eq!(parse_string_if_quoted("'é'").unwrap(), "é");
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("run  --features \"one, two\" -n 2"),
+            vec![
+                (0, String::from("run")),
+                (5, String::from("--features")),
+                (16, String::from("\"one, two\"")),
+                (27, String::from("-n")),
+                (30, String::from("2"))
+            ]
+        );
+        assert_eq!(
+            split_words("run 'a \\' b'"),
+            vec![(0, String::from("run")), (4, String::from("'a \\' b'"))]
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "example1"));
+        assert!(glob_match("example*", "example1"));
+        assert!(glob_match("ex*e?", "example1"));
+        assert!(glob_match("*1", "example1"));
+        assert!(glob_match("example1", "example1"));
+        assert!(!glob_match("example2", "example1"));
+        assert!(!glob_match("*2", "example1"));
+        assert!(!glob_match("example?", "example12"));
     }
 }
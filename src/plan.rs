@@ -0,0 +1,797 @@
+//! Functions to plan the runs of examples
+
+use crate::{
+    BuildType, CargoCommand,
+    cargo_toml::{self, Metadata},
+    command_line::SpecialArgs,
+    error::Error,
+    features, rust_file,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// An example in a package
+pub struct Example {
+    /// The name of the example
+    pub name: String,
+    /// The source file of the example
+    pub file: PathBuf,
+    /// The command used to run the example, before template arguments are substituted
+    pub command: CargoCommand,
+    /// The feature set that the example is run with, if feature sets are used
+    pub feature_set: Option<Vec<String>>,
+    /// The number of times the example is retried if it fails
+    pub retries: usize,
+}
+
+/// A package and its examples
+pub struct Package {
+    /// The directory containing the package's Cargo.toml
+    pub dir: PathBuf,
+    /// The name of the package, or None if the Cargo.toml is a virtual manifest
+    pub name: Option<String>,
+    /// The features that can be enabled for the package
+    pub available_features: Vec<String>,
+    /// The names of the package's dependencies
+    pub dependencies: Vec<String>,
+    /// The templated-examples metadata of the package
+    pub metadata: Metadata,
+    /// The examples in the package, with one entry for each target, build type and feature set
+    pub examples: Vec<Example>,
+    /// The values of template arguments
+    pub template_args: BTreeMap<String, Vec<String>>,
+    /// Groups of features that cannot be enabled together
+    pub mutually_exclusive_features: Vec<Vec<String>>,
+}
+
+/// A run of an example with values substituted for template arguments
+pub struct Run {
+    /// The directory containing the Cargo.toml of the package that the example belongs to
+    pub dir: PathBuf,
+    /// The name of the example
+    pub example: String,
+    /// The source file of the example
+    pub file: PathBuf,
+    /// The values of the template arguments used by the run
+    pub bindings: Vec<(String, String)>,
+    /// The command
+    pub command: CargoCommand,
+    /// The feature set used by the run, if feature sets are used
+    pub feature_set: Option<Vec<String>>,
+    /// The number of times the run is retried if it fails
+    pub retries: usize,
+}
+
+/// Describe a feature set, eg "[feature set: one, two]"
+pub fn describe_feature_set(feature_set: &[String]) -> String {
+    if feature_set.is_empty() {
+        String::from("[feature set: none]")
+    } else {
+        format!("[feature set: {}]", feature_set.join(", "))
+    }
+}
+
+impl Run {
+    /// Describe the run by its command and, if feature sets are used, its feature set
+    pub fn description(&self) -> String {
+        let c = self.command.as_string();
+        match &self.feature_set {
+            Some(f) => format!("{c} {}", describe_feature_set(f)),
+            None => c,
+        }
+    }
+}
+
+/// A group of runs whose examples can be built with a single `cargo build` command
+pub struct BuildGroup {
+    /// The command that builds all the examples in the group
+    pub command: String,
+    /// The runs in the group
+    pub runs: Vec<Run>,
+}
+
+impl Package {
+    /// Get the runs of every example, substituting all values of the template arguments
+    ///
+    /// Runs that enable more than one feature from a group of mutually exclusive features are
+    /// skipped.
+    pub fn runs(&self) -> Vec<Run> {
+        let mut runs = vec![];
+        for e in &self.examples {
+            let mut example_runs = vec![Run {
+                dir: self.dir.clone(),
+                example: e.name.clone(),
+                file: e.file.clone(),
+                bindings: vec![],
+                command: e.command.clone(),
+                feature_set: e.feature_set.clone(),
+                retries: e.retries,
+            }];
+            for arg in e.command.template_args() {
+                if let Some(options) = self.template_args.get(&arg) {
+                    let mut new_runs = vec![];
+                    for r in &example_runs {
+                        for o in options {
+                            let mut bindings = r.bindings.clone();
+                            bindings.push((arg.clone(), o.clone()));
+                            new_runs.push(Run {
+                                dir: r.dir.clone(),
+                                example: r.example.clone(),
+                                file: r.file.clone(),
+                                bindings,
+                                command: r.command.substitute(&arg, o),
+                                feature_set: r.feature_set.clone(),
+                                retries: r.retries,
+                            });
+                        }
+                    }
+                    example_runs = new_runs;
+                }
+            }
+            // Skip runs that enable more than one feature from a mutually exclusive group, once
+            // all the features in their commands are known
+            example_runs.retain(|r| {
+                !features::conflicts(r.command.features(), &self.mutually_exclusive_features)
+            });
+            runs.extend(example_runs);
+        }
+        runs
+    }
+}
+
+/// Get example command for a file
+fn get_example_command(metadata: &Metadata, eg: &str, file: &Path) -> Result<CargoCommand, Error> {
+    let file_command = rust_file::load_command(file, eg)?;
+    let cargo_toml_command = cargo_toml::load_command(metadata, eg)?;
+
+    // Return command
+    Ok(if let Some(c) = file_command {
+        if let Some(c2) = cargo_toml_command
+            && c != c2
+        {
+            // The command may have been set in Cargo.toml or in the configuration file
+            let source = c2
+                .location()
+                .and_then(|l| l.file.file_name())
+                .map_or(String::from("Cargo.toml"), |f| {
+                    f.to_string_lossy().to_string()
+                });
+            return Err(Error::new(format!(
+                "Commands set in file and {source} do not match for example \"{eg}\""
+            ))
+            .at(c.location().cloned())
+            .note(
+                format!("command set in {source} here"),
+                c2.location().cloned(),
+            ));
+        }
+        c
+    } else if let Some(c) = cargo_toml_command {
+        c
+    } else {
+        CargoCommand::new(String::from(eg))
+    })
+}
+
+/// Split the features passed on the command line into those that apply to a package and those
+/// that enable features of its dependencies
+///
+/// Features written as `package/feature` only apply to the package with that name. If `package` is
+/// instead a dependency, the feature is passed to cargo unchanged so that it enables the feature
+/// of the dependency.
+fn command_line_features(
+    features: &[String],
+    name: Option<&str>,
+    dependencies: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let mut own_features = vec![];
+    let mut dependency_features = vec![];
+    for f in features {
+        match f.split_once('/') {
+            Some((p, feature)) if Some(p) == name => {
+                own_features.push(String::from(feature));
+            }
+            Some((p, _)) if dependencies.iter().any(|d| d == p) => {
+                dependency_features.push(f.clone());
+            }
+            Some(_) => {}
+            None => {
+                own_features.push(f.clone());
+            }
+        }
+    }
+    (own_features, dependency_features)
+}
+
+/// Find the features passed using --features that do not apply to any package
+pub fn check_command_line_features(packages: &[Package], features: &[String]) -> Vec<Error> {
+    features
+        .iter()
+        .filter(|f| {
+            !packages.iter().any(|p| {
+                let (own, dependency) = command_line_features(
+                    std::slice::from_ref(*f),
+                    p.name.as_deref(),
+                    &p.dependencies,
+                );
+                !dependency.is_empty() || own.iter().any(|f| p.available_features.contains(f))
+            })
+        })
+        .map(|f| {
+            Error::warning(format!(
+                "Feature \"{f}\" passed using --features does not match any package"
+            ))
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Check that the preset passed using --preset is defined for at least one package
+pub fn check_preset(packages: &[Package], preset: Option<&str>) -> Option<Error> {
+    match preset {
+        Some(name)
+            if !packages
+                .iter()
+                .any(|p| p.metadata.presets().iter().any(|n| n == name)) =>
+        {
+            Some(Error::new(format!(
+                "Preset \"{name}\" passed using --preset is not defined"
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Load the examples of a package
+fn load_package(
+    dir: &Path,
+    special_args: &SpecialArgs,
+    cli_args: &BTreeMap<String, Vec<String>>,
+    is_member: bool,
+    errors: &mut Vec<Error>,
+) -> Result<Package, Error> {
+    let mut metadata = Metadata::load(
+        &dir,
+        special_args.config.as_deref().map(Path::new),
+        special_args.preset.as_deref(),
+    )?;
+    errors.extend(metadata.remove_invalid_values());
+    let default_builds = cargo_toml::get_default_builds(&metadata)?;
+    let profiles = cargo_toml::load_profiles(&dir)?;
+    let no_default_features = cargo_toml::get_default_flag(&metadata, "no-default-features")?;
+    let all_features = cargo_toml::get_default_flag(&metadata, "all-features")?;
+    let default_targets = cargo_toml::get_default_list(&metadata, "target")?;
+    let mut build_only_targets = cargo_toml::get_default_list(&metadata, "build-only-targets")?;
+    let name = cargo_toml::load_package_name(&dir)?;
+    let available_features = cargo_toml::load_available_features(&dir)?;
+    let dependencies = cargo_toml::load_dependencies(&dir)?;
+    let default_retries = cargo_toml::get_default_number(&metadata, "retries")?;
+    let default_feature_sets = cargo_toml::get_default_feature_sets(&metadata)?;
+    let mut excluded_features = cargo_toml::get_excluded_features(&metadata)?;
+    let mutually_exclusive_features = cargo_toml::get_mutually_exclusive_features(&metadata)?;
+
+    if let Some(b) = &special_args.build
+        && !b.is_defined(&profiles)
+    {
+        return Err(Error::new(format!(
+            "Profile \"{}\" passed using --profile is not defined",
+            b.name()
+        )));
+    }
+    build_only_targets.extend_from_slice(&special_args.build_only_targets);
+    excluded_features.extend_from_slice(&special_args.exclude_features);
+    let (own_features, dependency_features) =
+        command_line_features(&special_args.features, name.as_deref(), &dependencies);
+
+    let mut examples = vec![];
+    for (name, file) in cargo_toml::load_examples(&dir)? {
+        let mut load_example = || -> Result<(), Error> {
+            let mut c = get_example_command(&metadata, &name, &file)?;
+            if !c.build().is_defined(&profiles) {
+                return Err(Error::new(format!(
+                    "Profile \"{}\" used by example \"{name}\" is not defined",
+                    c.build().name()
+                ))
+                .at(c.location().cloned()));
+            }
+            if no_default_features || special_args.no_default_features {
+                c.set_no_default_features();
+            }
+            if all_features || special_args.all_features {
+                c.set_all_features();
+            }
+            let required_features = cargo_toml::load_required_features(&dir, &name)?;
+            c.set_required_features(&required_features)?;
+            c.set_available_features(&available_features);
+            c.add_features_if_available(&own_features);
+            c.add_features(&dependency_features);
+            if is_member {
+                c.set_manifest_path(&cargo_toml::manifest_path(&dir));
+            }
+
+            let retries = cargo_toml::load_retries(&metadata, &name)?
+                .or(special_args.retries)
+                .or(default_retries)
+                .unwrap_or(0);
+
+            // Run the example once for each build type. Build types set in the example's command
+            // or metadata are only replaced by --force-build
+            let builds = match &special_args.build {
+                Some(b) if special_args.force_build => vec![b.clone()],
+                _ if *c.build() != BuildType::Default => vec![c.build().clone()],
+                _ => match cargo_toml::load_builds(&metadata, &name)? {
+                    Some(b) => b,
+                    None => match &special_args.build {
+                        Some(b) => vec![b.clone()],
+                        None => default_builds.clone(),
+                    },
+                },
+            };
+
+            // Run the example once for each feature set, if feature sets are used. Combinations
+            // of features generated using --each-feature or --feature-powerset replace the
+            // feature sets set in Cargo.toml, and are used without the default features
+            let feature_sets = if let Some(m) = special_args.feature_combinations {
+                c.set_no_default_features();
+                let candidates = available_features
+                    .iter()
+                    .filter(|f| !excluded_features.contains(f))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                features::combinations(m, &candidates, &required_features, special_args.depth)
+                    .into_iter()
+                    .map(Some)
+                    .collect::<Vec<_>>()
+            } else {
+                match cargo_toml::load_feature_sets(&metadata, &name)? {
+                    Some(f) => f.into_iter().map(Some).collect::<Vec<_>>(),
+                    None => match &default_feature_sets {
+                        Some(f) => f.iter().cloned().map(Some).collect::<Vec<_>>(),
+                        None => vec![None],
+                    },
+                }
+            };
+
+            // Run the example once for each target
+            let targets = if c.target().is_some() {
+                vec![None]
+            } else {
+                let example_targets = cargo_toml::load_targets(&metadata, &name)?;
+                let targets = if !example_targets.is_empty() {
+                    example_targets
+                } else if !special_args.targets.is_empty() {
+                    special_args.targets.clone()
+                } else {
+                    default_targets.clone()
+                };
+                if targets.is_empty() {
+                    vec![None]
+                } else {
+                    targets.into_iter().map(Some).collect::<Vec<_>>()
+                }
+            };
+            for t in targets {
+                let mut c = c.clone();
+                if let Some(t) = t {
+                    c.set_target(&t);
+                }
+                if let Some(t) = c.target()
+                    && build_only_targets.iter().any(|b| b == t)
+                {
+                    c.set_build_only();
+                }
+                for b in &builds {
+                    for f in &feature_sets {
+                        let mut c = c.clone();
+                        c.force_build_type(b);
+                        if let Some(f) = f {
+                            c.add_features(f);
+                        }
+                        examples.push(Example {
+                            name: name.clone(),
+                            file: file.clone(),
+                            command: c,
+                            feature_set: f.clone(),
+                            retries,
+                        });
+                    }
+                }
+            }
+            Ok(())
+        };
+        if let Err(e) = load_example() {
+            errors.push(e);
+        }
+    }
+
+    let mut template_args = BTreeMap::new();
+    cargo_toml::load_args(&metadata, &mut template_args)?;
+    for (a, values) in cli_args {
+        template_args.insert(a.clone(), values.clone());
+    }
+
+    Ok(Package {
+        dir: PathBuf::from(dir),
+        name,
+        available_features,
+        dependencies,
+        metadata,
+        examples,
+        template_args,
+        mutually_exclusive_features,
+    })
+}
+
+/// Load all the packages in a directory, including the members of a workspace
+///
+/// If the directory is a workspace member, the examples are run using the --manifest-path of the member.
+/// Errors are added to `errors` and loading continues with the next example or package.
+/// The manifests that have already been loaded are recorded in `visited` so that a package that
+/// is reached more than once (eg a root package that is also listed as a member) is only loaded once.
+pub fn load_packages(
+    dir: &Path,
+    special_args: &SpecialArgs,
+    cli_args: &BTreeMap<String, Vec<String>>,
+    is_member: bool,
+    visited: &mut Vec<PathBuf>,
+    packages: &mut Vec<Package>,
+    errors: &mut Vec<Error>,
+) {
+    let manifest = cargo_toml::join(&dir, "Cargo.toml");
+    let manifest = fs::canonicalize(&manifest).unwrap_or(manifest);
+    if visited.contains(&manifest) {
+        return;
+    }
+    visited.push(manifest);
+
+    match cargo_toml::get_workspace(&dir) {
+        Ok(Some(w)) => {
+            for m in w {
+                load_packages(&m, special_args, cli_args, true, visited, packages, errors);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            errors.push(e);
+        }
+    }
+    match load_package(dir, special_args, cli_args, is_member, errors) {
+        Ok(p) => {
+            packages.push(p);
+        }
+        Err(e) => {
+            errors.push(e);
+        }
+    }
+}
+
+/// Load the runs of all examples in a directory
+///
+/// Runs with the same command are only included once. The number of duplicate runs that were
+/// dropped and any warnings are returned alongside the runs.
+pub fn load_runs(
+    dir: &Path,
+    special_args: &SpecialArgs,
+    cli_args: &BTreeMap<String, Vec<String>>,
+) -> Result<(Vec<Run>, usize, Vec<Error>), Vec<Error>> {
+    let mut packages = vec![];
+    let mut errors = vec![];
+    load_packages(
+        dir,
+        special_args,
+        cli_args,
+        false,
+        &mut vec![],
+        &mut packages,
+        &mut errors,
+    );
+    if !errors.is_empty() {
+        // Problems with options passed on the command line are found once for each package
+        let mut unique = vec![];
+        for e in errors {
+            if !unique.contains(&e) {
+                unique.push(e);
+            }
+        }
+        return Err(unique);
+    }
+    if let Some(e) = check_preset(&packages, special_args.preset.as_deref()) {
+        return Err(vec![e]);
+    }
+    let warnings = check_command_line_features(&packages, &special_args.features);
+    let mut runs = vec![];
+    let mut commands = vec![];
+    let mut duplicates = 0;
+    for r in packages.iter().flat_map(|p| p.runs()) {
+        let c = r.command.normalised().as_string();
+        if commands.contains(&c) {
+            duplicates += 1;
+        } else {
+            commands.push(c);
+            runs.push(r);
+        }
+    }
+    Ok((runs, duplicates, warnings))
+}
+
+/// Group runs that use the same package, features, profile and target
+///
+/// Groups are in the order that their first run appeared in, and the runs in each group keep
+/// their original order.
+pub fn group_runs(runs: Vec<Run>) -> Vec<BuildGroup> {
+    let mut groups: Vec<(String, Vec<Run>)> = vec![];
+    for r in runs {
+        let key = r.command.build_string(&[]);
+        if let Some((_, g)) = groups.iter_mut().find(|(k, _)| *k == key) {
+            g.push(r);
+        } else {
+            groups.push((key, vec![r]));
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, runs)| {
+            let mut examples = vec![];
+            for r in &runs {
+                let e = r.command.example_name();
+                if !examples.contains(&e) {
+                    examples.push(e);
+                }
+            }
+            BuildGroup {
+                command: runs[0].command.build_string(&examples),
+                runs,
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{command_line, temp_dir::TempDir};
+
+    fn load(dir: &Path, args: &[&str]) -> Package {
+        let (special_args, cli_args) =
+            command_line::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>()).unwrap();
+        let mut packages = vec![];
+        let mut errors = vec![];
+        load_packages(
+            dir,
+            &special_args,
+            &cli_args,
+            false,
+            &mut vec![],
+            &mut packages,
+            &mut errors,
+        );
+        assert!(errors.is_empty());
+        packages
+            .into_iter()
+            .find(|p| p.name.as_deref() == Some("member"))
+            .unwrap()
+    }
+
+    fn example<'a>(p: &'a Package, name: &str) -> &'a Example {
+        p.examples.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"
+[workspace]
+members = ["member"]
+
+[workspace.metadata.templated-examples]
+A = ["workspace"]
+B = ["workspace"]
+C = ["workspace"]
+retries = 7
+"#,
+        );
+        dir.write(
+            "templated-examples.toml",
+            r#"
+A = ["file"]
+B = ["file"]
+C = ["file"]
+D = ["file"]
+E = ["file"]
+
+[example.demo]
+build = "debug"
+retries = 1
+"#,
+        );
+        dir.write(
+            "member/Cargo.toml",
+            r#"
+[package]
+name = "member"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata.templated-examples]
+B = ["package"]
+C = ["package"]
+
+[package.metadata.templated-examples.presets.ci]
+C = ["preset"]
+D = ["preset"]
+retries = 5
+build = "release"
+
+[package.metadata.example.demo.templated-examples]
+retries = 2
+"#,
+        );
+        for e in ["demo", "other"] {
+            dir.write(&format!("member/examples/{e}.rs"), "fn main() {}\n");
+        }
+        dir.write("ci.toml", "E = [\"config\"]\n");
+
+        let value = |p: &Package, a: &str| p.template_args[a].clone();
+
+        // file < workspace < package
+        let p = load(dir.path(), &[]);
+        assert_eq!(value(&p, "A"), ["workspace"]);
+        assert_eq!(value(&p, "B"), ["package"]);
+        assert_eq!(value(&p, "C"), ["package"]);
+        assert_eq!(value(&p, "E"), ["file"]);
+        assert_eq!(example(&p, "other").retries, 7);
+
+        // package < preset < example < template values passed on the command line
+        let p = load(dir.path(), &["--preset", "ci", "D", "cli"]);
+        assert_eq!(value(&p, "C"), ["preset"]);
+        assert_eq!(value(&p, "D"), ["cli"]);
+        assert_eq!(example(&p, "other").retries, 5);
+        assert_eq!(example(&p, "other").command.build().name(), "release");
+        // Options set for the example in Cargo.toml override those set in the file
+        assert_eq!(example(&p, "demo").retries, 2);
+        assert_eq!(example(&p, "demo").command.build().name(), "debug");
+
+        // Options passed on the command line override crate-wide options but not those of an
+        // example
+        let p = load(dir.path(), &["--preset", "ci", "--retries", "3"]);
+        assert_eq!(example(&p, "other").retries, 3);
+        assert_eq!(example(&p, "demo").retries, 2);
+
+        // --config replaces the file that is found in the workspace
+        let path = dir.join("ci.toml").display().to_string();
+        let p = load(dir.path(), &["--config", &path]);
+        assert_eq!(value(&p, "E"), ["config"]);
+        assert!(!p.template_args.contains_key("D"));
+        assert_eq!(example(&p, "demo").retries, 2);
+        assert_eq!(example(&p, "demo").command.build().name(), "release");
+    }
+
+    #[test]
+    fn test_example_options_with_file_command() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"
+[package]
+name = "member"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+one = []
+
+[package.metadata.example.retried.templated-examples]
+retries = 2
+
+[package.metadata.example.cross.templated-examples]
+target = "wasm32-wasip1"
+feature-sets = [["one"]]
+
+[package.metadata.example.mismatched.templated-examples]
+build = "debug"
+retries = 2
+"#,
+        );
+        for e in ["retried", "cross", "mismatched"] {
+            dir.write(
+                &format!("examples/{e}.rs"),
+                "//? run --release\nfn main() {}\n",
+            );
+        }
+
+        // Options other than the command and build type are applied to the command in the file
+        let metadata = Metadata::load(&dir.path(), None, None).unwrap();
+        let file = |e: &str| dir.join(&format!("examples/{e}.rs"));
+        let c = get_example_command(&metadata, "retried", &file("retried")).unwrap();
+        assert_eq!(c.as_string(), "cargo run --example retried --release");
+        assert!(get_example_command(&metadata, "cross", &file("cross")).is_ok());
+        assert!(get_example_command(&metadata, "mismatched", &file("mismatched")).is_err());
+
+        let (special_args, cli_args) = command_line::parse(&[]).unwrap();
+        let mut packages = vec![];
+        let mut errors = vec![];
+        load_packages(
+            dir.path(),
+            &special_args,
+            &cli_args,
+            false,
+            &mut vec![],
+            &mut packages,
+            &mut errors,
+        );
+        assert_eq!(errors.len(), 1);
+        let retried = example(&packages[0], "retried");
+        assert_eq!(retried.retries, 2);
+        assert_eq!(retried.command.build().name(), "release");
+        let cross = example(&packages[0], "cross");
+        assert_eq!(
+            cross.command.as_string(),
+            "cargo run --example cross --features \"one\" --release --target wasm32-wasip1"
+        );
+    }
+
+    #[test]
+    fn test_mutually_exclusive_features() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"
+[package]
+name = "member"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+a = []
+b = []
+c = []
+
+[package.metadata.templated-examples]
+F = ["c"]
+mutually-exclusive-features = [["b", "c"]]
+"#,
+        );
+        dir.write(
+            "examples/templated.rs",
+            "//? run --features \"{{F}}\"\nfn main() {}\n",
+        );
+        dir.write(
+            "examples/fixed.rs",
+            "//? run --features \"c\"\nfn main() {}\n",
+        );
+
+        // Features in the command, including those substituted for template arguments, are
+        // checked against the groups as well as the generated features
+        let p = load(dir.path(), &["--feature-powerset"]);
+        let runs = p.runs();
+        for r in &runs {
+            assert!(!features::conflicts(
+                r.command.features(),
+                &[vec![String::from("b"), String::from("c")]]
+            ));
+        }
+        for e in ["templated", "fixed"] {
+            let sets = runs
+                .iter()
+                .filter(|r| r.example == e)
+                .map(|r| r.feature_set.clone().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(sets, [vec!["a"; 0], vec!["a"], vec!["c"], vec!["a", "c"]]);
+        }
+    }
+
+    #[test]
+    fn test_command_line_features() {
+        let features = ["one", "example/two", "other/three", "serde/derive"]
+            .map(String::from)
+            .to_vec();
+        let (own, dependency) =
+            command_line_features(&features, Some("example"), &[String::from("serde")]);
+        assert_eq!(own, vec!["one", "two"]);
+        assert_eq!(dependency, vec!["serde/derive"]);
+    }
+}
@@ -1,18 +1,29 @@
 //! Functions to read information from a rust file
 
-use crate::CargoCommand;
-use crate::cargo_toml::join;
+use crate::{
+    CargoCommand,
+    error::{Error, Location},
+};
 use std::{fs, path::Path};
 
 /// Load command from file (line starting //?)
-pub fn load_command(dir: &impl AsRef<Path>, eg: &str) -> Option<CargoCommand> {
-    for line in fs::read_to_string(join(&join(dir, "examples"), &format!("{eg}.rs")))
-        .expect("Error reading example file")
-        .lines()
-    {
+pub fn load_command(file: &Path, eg: &str) -> Result<Option<CargoCommand>, Error> {
+    let contents = fs::read_to_string(file).map_err(|e| {
+        Error::new(format!(
+            "Could not read file {} for example \"{eg}\": {e}",
+            file.display()
+        ))
+    })?;
+    for (n, line) in contents.lines().enumerate() {
         if let Some(c) = line.strip_prefix("//? ") {
-            return Some(CargoCommand::from_str(c, eg));
+            let location = Location {
+                file: file.to_path_buf(),
+                line: n + 1,
+                column: 5,
+                length: c.chars().count(),
+            };
+            return CargoCommand::from_str(c, eg, Some(location)).map(Some);
         }
     }
-    None
+    Ok(None)
 }
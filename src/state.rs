@@ -0,0 +1,252 @@
+//! The outcomes of the most recent runs, used to re-run only the runs that failed
+
+use crate::{Outcome, Usage, cargo_toml, error::Error, plan::Run};
+use serde_json::{Map, Value, json};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The package, example, template bindings, feature set, build type and target that identify a run
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunId {
+    /// The directory containing the Cargo.toml of the package
+    pub package: PathBuf,
+    /// The name of the example
+    pub example: String,
+    /// The values of the template arguments
+    pub bindings: Vec<(String, String)>,
+    /// The feature set, if feature sets are used
+    pub feature_set: Option<Vec<String>>,
+    /// The name of the build type, eg "release"
+    pub build: Option<String>,
+    /// The target triple, if the run is for a target passed using --target
+    pub target: Option<String>,
+}
+
+/// Get the identifier of a run
+pub fn id(run: &Run) -> RunId {
+    RunId {
+        package: run.dir.clone(),
+        example: run.example.clone(),
+        bindings: run.bindings.clone(),
+        feature_set: run.feature_set.clone(),
+        build: Some(run.command.build().name().to_string()),
+        target: run.command.target().map(String::from),
+    }
+}
+
+/// Convert the identifier of a run to a JSON object
+pub fn id_to_json(id: &RunId) -> Map<String, Value> {
+    let mut r = Map::new();
+    r.insert(
+        String::from("package"),
+        json!(id.package.display().to_string()),
+    );
+    r.insert(String::from("example"), json!(id.example));
+    r.insert(
+        String::from("bindings"),
+        json!(id.bindings.iter().map(|(n, v)| [n, v]).collect::<Vec<_>>()),
+    );
+    if let Some(f) = &id.feature_set {
+        r.insert(String::from("feature_set"), json!(f));
+    }
+    if let Some(b) = &id.build {
+        r.insert(String::from("build"), json!(b));
+    }
+    if let Some(t) = &id.target {
+        r.insert(String::from("target"), json!(t));
+    }
+    r
+}
+
+/// Read the identifier of a run from a JSON object
+///
+/// Runs that were saved before feature sets, build types and targets were recorded do not have
+/// them.
+pub fn id_from_json(r: &Value) -> Option<RunId> {
+    let bindings = r["bindings"]
+        .as_array()?
+        .iter()
+        .map(|b| Some((b[0].as_str()?.to_string(), b[1].as_str()?.to_string())))
+        .collect::<Option<Vec<_>>>()?;
+    let feature_set = match &r["feature_set"] {
+        Value::Null => None,
+        f => Some(
+            f.as_array()?
+                .iter()
+                .map(|f| f.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()?,
+        ),
+    };
+    let build = match &r["build"] {
+        Value::Null => None,
+        b => Some(b.as_str()?.to_string()),
+    };
+    let target = match &r["target"] {
+        Value::Null => None,
+        t => Some(t.as_str()?.to_string()),
+    };
+    Some(RunId {
+        package: PathBuf::from(r["package"].as_str()?),
+        example: r["example"].as_str()?.to_string(),
+        bindings,
+        feature_set,
+        build,
+        target,
+    })
+}
+
+/// Get the name used to store an outcome
+fn outcome_name(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Passed => "passed",
+        Outcome::Flaky => "flaky",
+        Outcome::Built => "built",
+        Outcome::Failed => "failed",
+        Outcome::BuildFailed => "build-failed",
+        Outcome::Skipped => "skipped",
+        Outcome::NotStarted => "not-started",
+    }
+}
+
+/// The outcomes of the most recent runs
+pub struct State {
+    /// The file that the state is stored in
+    file: PathBuf,
+    /// The most recent outcome of each run, and the time and resources it used
+    outcomes: Vec<(RunId, String, Value)>,
+}
+
+impl State {
+    /// Load the outcomes of the most recent runs for the packages in a directory
+    ///
+    /// The outcomes are stored in the target directory. If they do not exist yet, no runs are
+    /// recorded.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        Self::read(cargo_toml::join(
+            &cargo_toml::join(&cargo_toml::target_dir(&dir)?, "templated-examples"),
+            "last-run.json",
+        ))
+    }
+
+    /// Read the outcomes from a file
+    fn read(file: PathBuf) -> Result<Self, Error> {
+        let mut outcomes = vec![];
+        if let Ok(contents) = fs::read_to_string(&file) {
+            let error = || {
+                Error::new(format!(
+                    "Could not read the outcomes of the last run from {}",
+                    file.display()
+                ))
+            };
+            let runs = serde_json::from_str::<Value>(&contents).map_err(|_| error())?;
+            for r in runs.as_array().ok_or_else(error)? {
+                match (id_from_json(r), r["outcome"].as_str()) {
+                    (Some(id), Some(outcome)) => {
+                        outcomes.push((id, outcome.to_string(), r["usage"].clone()));
+                    }
+                    _ => {
+                        return Err(error());
+                    }
+                }
+            }
+        }
+        Ok(Self { file, outcomes })
+    }
+
+    /// Did the most recent run with the same identifier fail?
+    ///
+    /// Runs that were never started because too many other runs failed are included.
+    pub fn failed(&self, run: &Run) -> bool {
+        let id = id(run);
+        self.outcomes.iter().any(|(i, o, _)| {
+            *i == id && (o == "failed" || o == "build-failed" || o == "not-started")
+        })
+    }
+
+    /// Forget all recorded outcomes
+    pub fn clear(&mut self) {
+        self.outcomes.clear();
+    }
+
+    /// Record the outcome of a run, replacing any earlier outcome of the same run
+    pub fn record(&mut self, run: &Run, outcome: Outcome, usage: Option<&Usage>) {
+        let id = id(run);
+        let outcome = outcome_name(outcome).to_string();
+        let usage = usage.map_or(Value::Null, Usage::to_json);
+        if let Some(o) = self.outcomes.iter_mut().find(|(i, _, _)| *i == id) {
+            o.1 = outcome;
+            o.2 = usage;
+        } else {
+            self.outcomes.push((id, outcome, usage));
+        }
+    }
+
+    /// Save the outcomes
+    pub fn save(&self) -> Result<(), Error> {
+        let error = |e: std::io::Error| {
+            Error::new(format!(
+                "Could not save the outcomes of the runs to {}: {e}",
+                self.file.display()
+            ))
+        };
+        let runs = self
+            .outcomes
+            .iter()
+            .map(|(id, outcome, usage)| {
+                let mut r = id_to_json(id);
+                r.insert(String::from("outcome"), json!(outcome));
+                r.insert(String::from("usage"), usage.clone());
+                Value::from(r)
+            })
+            .collect::<Vec<_>>();
+        if let Some(d) = self.file.parent() {
+            fs::create_dir_all(d).map_err(error)?;
+        }
+        fs::write(&self.file, format!("{:#}\n", Value::from(runs))).map_err(error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CargoCommand, temp_dir::TempDir};
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new();
+        let run = |n: &str| Run {
+            dir: dir.path().to_path_buf(),
+            example: String::from("example"),
+            file: dir.join("example.rs"),
+            bindings: vec![(String::from("N"), String::from(n))],
+            command: CargoCommand::from_str("run --release", "example", None).unwrap(),
+            feature_set: None,
+            retries: 0,
+        };
+        let mut state = State {
+            file: dir.join("last-run.json"),
+            outcomes: vec![],
+        };
+        state.record(&run("1"), Outcome::Passed, Some(&Usage::default()));
+        state.record(&run("2"), Outcome::Passed, None);
+        state.record(&run("2"), Outcome::BuildFailed, None);
+        let mut with_features = run("1");
+        with_features.feature_set = Some(vec![String::from("one")]);
+        state.record(&with_features, Outcome::Failed, None);
+        let mut with_target = run("1");
+        with_target.command.set_target("wasm32-wasip1");
+        state.record(&with_target, Outcome::Failed, None);
+        state.save().unwrap();
+
+        let state = State::read(dir.join("last-run.json")).unwrap();
+        assert!(!state.failed(&run("1")));
+        assert!(state.failed(&run("2")));
+        assert!(!state.failed(&run("3")));
+        assert!(state.failed(&with_features));
+        assert!(state.failed(&with_target));
+        assert_eq!(state.outcomes[0].2["wall_time"], 0.0);
+        assert_eq!(state.outcomes[1].2, Value::Null);
+    }
+}
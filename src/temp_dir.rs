@@ -0,0 +1,53 @@
+//! Temporary directories used by tests
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The number of temporary directories created so far by this process
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory that is removed when it is dropped, even if a test fails
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a new empty directory
+    pub fn new() -> Self {
+        let path = env::temp_dir().join(format!(
+            "templated-examples-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Get the path of the directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path of a file in the directory
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.path.join(file)
+    }
+
+    /// Write a file in the directory, creating its parent directories
+    pub fn write(&self, file: &str, contents: &str) {
+        let path = self.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
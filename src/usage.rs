@@ -0,0 +1,144 @@
+//! Measure the time and resources used by runs
+
+use serde_json::{Value, json};
+use std::{
+    io,
+    process::Child,
+    time::{Duration, Instant},
+};
+
+/// The time and resources used by a run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    /// Wall-clock time
+    pub wall: Duration,
+    /// User CPU time, if it could be measured
+    pub user: Option<Duration>,
+    /// System CPU time, if it could be measured
+    pub system: Option<Duration>,
+    /// Peak resident set size in bytes, if it could be measured
+    pub max_rss: Option<u64>,
+}
+
+/// Format a duration in seconds, or in milliseconds if it is shorter than a second
+pub fn format_duration(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", d.as_secs_f64())
+    }
+}
+
+/// Format a number of bytes in megabytes
+pub fn format_bytes(b: u64) -> String {
+    format!("{:.1} MB", b as f64 / 1_000_000.0)
+}
+
+impl Usage {
+    /// Add the usage of another run, eg a retry of the same example
+    pub fn add(&mut self, other: &Usage) {
+        let add = |a: Option<Duration>, b: Option<Duration>| Some(a? + b?);
+        self.wall += other.wall;
+        self.user = add(self.user, other.user);
+        self.system = add(self.system, other.system);
+        self.max_rss = match (self.max_rss, other.max_rss) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+    }
+
+    /// Summarise in a single line
+    pub fn summary(&self) -> String {
+        let mut s = format_duration(self.wall);
+        if let (Some(user), Some(system)) = (self.user, self.system) {
+            s.push_str(&format!(
+                " (user {}, system {})",
+                format_duration(user),
+                format_duration(system)
+            ));
+        }
+        if let Some(rss) = self.max_rss {
+            s.push_str(&format!(", max RSS {}", format_bytes(rss)));
+        }
+        s
+    }
+
+    /// Convert to JSON, with times in seconds and memory in bytes
+    pub fn to_json(&self) -> Value {
+        json!({
+            "wall_time": self.wall.as_secs_f64(),
+            "user_time": self.user.map(|d| d.as_secs_f64()),
+            "system_time": self.system.map(|d| d.as_secs_f64()),
+            "max_rss": self.max_rss,
+        })
+    }
+}
+
+/// Wait for a child process to finish, returning whether it was successful and its usage
+///
+/// On Linux, the CPU time and peak memory use are measured using `wait4`. On other platforms,
+/// only the wall-clock time is measured.
+pub fn wait(child: &mut Child, start: Instant) -> io::Result<(bool, Usage)> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut status = 0;
+        // SAFETY: rusage is a plain C struct that is valid when zeroed
+        let mut rusage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        // SAFETY: the pointers are to valid local variables, and the child has not been waited for
+        let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut rusage) };
+        if pid < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let time = |t: libc::timeval| {
+            Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+        };
+        Ok((
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+            Usage {
+                wall: start.elapsed(),
+                user: Some(time(rusage.ru_utime)),
+                system: Some(time(rusage.ru_stime)),
+                // ru_maxrss is in kilobytes on Linux
+                max_rss: Some(rusage.ru_maxrss as u64 * 1024),
+            },
+        ))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let status = child.wait()?;
+        Ok((
+            status.code() == Some(0),
+            Usage {
+                wall: start.elapsed(),
+                ..Usage::default()
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        let mut u = Usage {
+            wall: Duration::from_millis(1500),
+            user: Some(Duration::from_secs(1)),
+            system: Some(Duration::from_millis(200)),
+            max_rss: Some(5_000_000),
+        };
+        u.add(&Usage {
+            wall: Duration::from_secs(1),
+            user: Some(Duration::from_millis(500)),
+            system: None,
+            max_rss: Some(8_000_000),
+        });
+        assert_eq!(u.wall, Duration::from_millis(2500));
+        assert_eq!(u.user, Some(Duration::from_millis(1500)));
+        assert_eq!(u.system, None);
+        assert_eq!(u.max_rss, Some(8_000_000));
+        assert_eq!(u.summary(), "2.50s, max RSS 8.0 MB");
+        assert_eq!(format_duration(Duration::from_micros(1200)), "1.2ms");
+    }
+}